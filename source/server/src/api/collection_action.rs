@@ -1,7 +1,13 @@
-use crate::lib::json::types::JsonObject;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::lib::json::types::{JsonDeserializationError, JsonObject, SmartJson};
+use crate::page::error::WriteError;
 use crate::storage::collection::Collection;
+use crate::storage::view::ViewDefinition;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use serde_json::Value;
 
 /// An action is something that mutates a database collection.
 ///
@@ -15,7 +21,21 @@ where
     /// The action name as a string.
     fn name(&self) -> String;
     /// The logic to perform when the action is dispatched.
-    fn handle(&self, ctx: CollectionActionContext<I>) -> O;
+    fn handle(&self, ctx: CollectionActionContext<I>) -> Result<O, WriteError>;
+}
+
+/// An action that only reads a database collection. Read actions of a collection can run at the
+/// same time, and never change the catalog. Read actions can also be dispatched on a view, in
+/// which case they read the source collection and must run its documents through the view.
+pub trait CollectionReadAction<I, O>
+where
+    I: DeserializeOwned,
+    O: Serialize,
+{
+    /// The action name as a string.
+    fn name(&self) -> String;
+    /// The logic to perform when the action is dispatched.
+    fn handle(&self, ctx: CollectionReadContext<I>) -> Result<O, WriteError>;
 }
 
 pub struct QueryFormat {
@@ -24,39 +44,423 @@ pub struct QueryFormat {
 }
 
 /// A wrapper for all context objects when executing a collection action.
-pub struct CollectionActionContext<I>
+pub struct CollectionActionContext<'a, I>
+where
+    I: DeserializeOwned,
+{
+    input: I,
+    collection: &'a mut Collection,
+}
+
+impl<'a, I> CollectionActionContext<'a, I>
+where
+    I: DeserializeOwned,
+{
+    pub fn new(input: I, collection: &'a mut Collection) -> Self {
+        CollectionActionContext { input, collection }
+    }
+}
+
+/// A wrapper for all context objects when executing a collection read action.
+pub struct CollectionReadContext<'a, I>
 where
     I: DeserializeOwned,
 {
     input: I,
-    collection: Collection,
+    collection: &'a Collection,
+    /// The view the action was dispatched on, if it was not dispatched on the collection itself.
+    view: Option<&'a ViewDefinition>,
+}
+
+impl<'a, I> CollectionReadContext<'a, I>
+where
+    I: DeserializeOwned,
+{
+    pub fn new(input: I, collection: &'a Collection, view: Option<&'a ViewDefinition>) -> Self {
+        CollectionReadContext {
+            input,
+            collection,
+            view,
+        }
+    }
+}
+
+/// Error that occurs when dispatching an action by name.
+pub enum ActionError {
+    /// No action with the name is registered.
+    NotFound(String),
+    /// The action input could not be deserialized.
+    Input(JsonDeserializationError),
+    /// The action failed.
+    Write(WriteError),
+}
+
+/// A collection action with its input and output types erased, so actions with different types
+/// can be looked up by name.
+trait DynCollectionAction: Send + Sync {
+    fn dispatch(&self, input: Value, collection: &mut Collection) -> Result<Value, ActionError>;
+}
+
+/// A collection read action with its input and output types erased.
+trait DynCollectionReadAction: Send + Sync {
+    fn dispatch(
+        &self,
+        input: Value,
+        collection: &Collection,
+        view: Option<&ViewDefinition>,
+    ) -> Result<Value, ActionError>;
+}
+
+struct ErasedAction<A, I, O> {
+    action: A,
+    types: PhantomData<fn(I) -> O>,
+}
+
+impl<A, I, O> DynCollectionAction for ErasedAction<A, I, O>
+where
+    A: CollectionAction<I, O> + Send + Sync,
+    I: DeserializeOwned,
+    O: Serialize,
+{
+    fn dispatch(&self, input: Value, collection: &mut Collection) -> Result<Value, ActionError> {
+        let input: I = SmartJson::from(input)
+            .into_struct()
+            .map_err(ActionError::Input)?;
+
+        let output = collection
+            .dispatch_action(&self.action, input)
+            .map_err(ActionError::Write)?;
+
+        Ok(serde_json::to_value(output).unwrap())
+    }
+}
+
+struct ErasedReadAction<A, I, O> {
+    action: A,
+    types: PhantomData<fn(I) -> O>,
+}
+
+impl<A, I, O> DynCollectionReadAction for ErasedReadAction<A, I, O>
+where
+    A: CollectionReadAction<I, O> + Send + Sync,
+    I: DeserializeOwned,
+    O: Serialize,
+{
+    fn dispatch(
+        &self,
+        input: Value,
+        collection: &Collection,
+        view: Option<&ViewDefinition>,
+    ) -> Result<Value, ActionError> {
+        let input: I = SmartJson::from(input)
+            .into_struct()
+            .map_err(ActionError::Input)?;
+
+        let output = collection
+            .dispatch_read_action(&self.action, input, view)
+            .map_err(ActionError::Write)?;
+
+        Ok(serde_json::to_value(output).unwrap())
+    }
+}
+
+/// The collection actions that can be dispatched by name. Action names are not case sensitive.
+pub struct ActionRegistry {
+    actions: HashMap<String, Box<dyn DynCollectionAction>>,
+    read_actions: HashMap<String, Box<dyn DynCollectionReadAction>>,
+}
+
+impl ActionRegistry {
+    /// A registry without any actions.
+    pub fn new() -> Self {
+        ActionRegistry {
+            actions: HashMap::new(),
+            read_actions: HashMap::new(),
+        }
+    }
+
+    /// A registry with every action built into the server.
+    pub fn builtin() -> Self {
+        let mut registry = ActionRegistry::new();
+        registry.register(actions::Insert);
+        registry.register_read(actions::Find);
+        registry.register(actions::Update);
+
+        registry
+    }
+
+    /// Registers an action, replacing any action with the same name.
+    pub fn register<A, I, O>(&mut self, action: A)
+    where
+        A: CollectionAction<I, O> + Send + Sync + 'static,
+        I: DeserializeOwned + 'static,
+        O: Serialize + 'static,
+    {
+        let action = ErasedAction {
+            action,
+            types: PhantomData,
+        };
+
+        let name = action.action.name().to_lowercase();
+        self.read_actions.remove(&name);
+        self.actions.insert(name, Box::new(action));
+    }
+
+    /// Registers a read action, replacing any action with the same name.
+    pub fn register_read<A, I, O>(&mut self, action: A)
+    where
+        A: CollectionReadAction<I, O> + Send + Sync + 'static,
+        I: DeserializeOwned + 'static,
+        O: Serialize + 'static,
+    {
+        let action = ErasedReadAction {
+            action,
+            types: PhantomData,
+        };
+
+        let name = action.action.name().to_lowercase();
+        self.actions.remove(&name);
+        self.read_actions.insert(name, Box::new(action));
+    }
+
+    /// True if an action is registered with the name.
+    pub fn contains(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.actions.contains_key(&name) || self.read_actions.contains_key(&name)
+    }
+
+    /// True if a read action is registered with the name.
+    pub fn is_read(&self, name: &str) -> bool {
+        self.read_actions.contains_key(&name.to_lowercase())
+    }
+
+    /// Names of every registered action, in no particular order.
+    pub fn names(&self) -> Vec<&String> {
+        self.actions
+            .keys()
+            .chain(self.read_actions.keys())
+            .collect()
+    }
+
+    /// Deserializes the input of an action and runs the action against a collection, returning
+    /// the serialized output.
+    pub fn dispatch(
+        &self,
+        name: &str,
+        input: Value,
+        collection: &mut Collection,
+    ) -> Result<Value, ActionError> {
+        let key = name.to_lowercase();
+        match self.actions.get(&key) {
+            Some(action) => action.dispatch(input, collection),
+            None => self.dispatch_read(name, input, collection, None),
+        }
+    }
+
+    /// Deserializes the input of a read action and runs the action against a collection, or
+    /// against a view of the collection, returning the serialized output. Actions that write are
+    /// not found.
+    pub fn dispatch_read(
+        &self,
+        name: &str,
+        input: Value,
+        collection: &Collection,
+        view: Option<&ViewDefinition>,
+    ) -> Result<Value, ActionError> {
+        match self.read_actions.get(&name.to_lowercase()) {
+            Some(action) => action.dispatch(input, collection, view),
+            None => Err(ActionError::NotFound(name.to_string())),
+        }
+    }
 }
 
 pub mod actions {
-    use super::{CollectionAction, CollectionActionContext};
+    use super::{
+        CollectionAction, CollectionActionContext, CollectionReadAction, CollectionReadContext,
+    };
     use crate::lib::json::types::JsonObject;
+    use crate::page::error::WriteError;
+    use crate::page::wal::Durability;
+    use crate::storage::document::Document;
+    use crate::storage::query::Filter;
+    use crate::storage::update;
     use serde::{Deserialize, Serialize};
+    use serde_json::Value;
 
-    /// Create a new document and store it in a collection.
+    /// Create new documents and store them in a collection.
     pub struct Insert;
 
+    #[derive(Deserialize, Serialize)]
+    #[serde(untagged)]
+    /// A single document or a list of documents.
+    pub enum InsertData {
+        One(JsonObject),
+        Many(Vec<JsonObject>),
+    }
+
     #[derive(Deserialize, Serialize)]
     pub struct InsertInput {
-        pub data: JsonObject,
+        pub data: InsertData,
+        /// The durability of the write, if stronger than the collection's.
+        pub durability: Option<Durability>,
     }
 
     #[derive(Serialize)]
-    pub struct InsertOutput {}
+    pub struct InsertOutput {
+        /// The `_id` of every inserted document, in the order the documents were given.
+        pub ids: Vec<Value>,
+        /// The durability actually applied to the write.
+        pub durability: Durability,
+    }
 
     impl CollectionAction<InsertInput, InsertOutput> for Insert {
         fn name(&self) -> String {
             "Insert".to_string()
         }
 
-        fn handle(&self, ctx: CollectionActionContext<InsertInput>) -> InsertOutput {
+        fn handle(
+            &self,
+            ctx: CollectionActionContext<InsertInput>,
+        ) -> Result<InsertOutput, WriteError> {
+            let CollectionActionContext { input, collection } = ctx;
+
+            let documents = match input.data {
+                InsertData::One(data) => vec![Document::new(data)],
+                InsertData::Many(data) => data.into_iter().map(Document::new).collect(),
+            };
+
+            let (inserted, durability) = collection.insert_many(documents, input.durability)?;
+
+            Ok(InsertOutput {
+                ids: inserted.into_iter().map(|(_, id)| id).collect(),
+                durability,
+            })
+        }
+    }
+
+    /// Read the documents of a collection or view that match a filter.
+    pub struct Find;
+
+    #[derive(Deserialize, Serialize)]
+    pub struct FindInput {
+        /// The filter the documents must match. Every document matches an empty filter.
+        #[serde(default)]
+        pub filter: Filter,
+        /// The amount of matching documents to leave out.
+        #[serde(default)]
+        pub skip: usize,
+        /// The most documents to return.
+        pub limit: Option<usize>,
+    }
+
+    #[derive(Serialize)]
+    pub struct FindOutput {
+        pub documents: Vec<JsonObject>,
+    }
+
+    impl CollectionReadAction<FindInput, FindOutput> for Find {
+        fn name(&self) -> String {
+            "Find".to_string()
+        }
+
+        fn handle(&self, ctx: CollectionReadContext<FindInput>) -> Result<FindOutput, WriteError> {
+            let CollectionReadContext {
+                input,
+                collection,
+                view,
+            } = ctx;
+            let limit = input.limit.unwrap_or(usize::MAX);
+
+            // The pipeline of a view needs every document of the source collection that matches
+            // the view query, so only a collection is read up to the limit.
+            if let Some(view) = view {
+                let mut documents = Vec::new();
+                collection.pages().scan(|_, document| {
+                    if view.query.matches(document.as_json()) {
+                        documents.push(document.as_json().clone());
+                    }
+                    true
+                })?;
+
+                let documents = view
+                    .run(documents)
+                    .into_iter()
+                    .filter(|document| input.filter.matches(document))
+                    .skip(input.skip)
+                    .take(limit)
+                    .collect();
+
+                return Ok(FindOutput { documents });
+            }
+
+            let mut documents = Vec::new();
+            let mut skip = input.skip;
+            if limit > 0 {
+                collection.pages().scan(|_, document| {
+                    if !input.filter.matches(document.as_json()) {
+                        return true;
+                    }
+                    if skip > 0 {
+                        skip -= 1;
+                        return true;
+                    }
+
+                    documents.push(document.as_json().clone());
+                    documents.len() < limit
+                })?;
+            }
+
+            Ok(FindOutput { documents })
+        }
+    }
+
+    /// Change the first document of a collection that matches a filter, or every matching
+    /// document.
+    pub struct Update;
+
+    #[derive(Deserialize, Serialize)]
+    pub struct UpdateInput {
+        /// The filter the documents must match. Every document matches an empty filter.
+        #[serde(default)]
+        pub filter: Filter,
+        /// The update operators to apply.
+        pub update: update::Update,
+        /// True to update every matching document instead of only the first.
+        #[serde(default)]
+        pub many: bool,
+        /// The durability of the write, if stronger than the collection's.
+        pub durability: Option<Durability>,
+    }
+
+    #[derive(Serialize)]
+    pub struct UpdateOutput {
+        /// The amount of documents that matched the filter.
+        pub matched: u64,
+        /// The amount of matched documents that the update changed.
+        pub modified: u64,
+        /// The durability actually applied to the write.
+        pub durability: Durability,
+    }
+
+    impl CollectionAction<UpdateInput, UpdateOutput> for Update {
+        fn name(&self) -> String {
+            "Update".to_string()
+        }
+
+        fn handle(
+            &self,
+            ctx: CollectionActionContext<UpdateInput>,
+        ) -> Result<UpdateOutput, WriteError> {
             let CollectionActionContext { input, collection } = ctx;
 
-            InsertOutput {}
+            let (matched, modified, durability) =
+                collection.update(&input.filter, &input.update, input.many, input.durability)?;
+
+            Ok(UpdateOutput {
+                matched,
+                modified,
+                durability,
+            })
         }
     }
 }
@@ -0,0 +1,32 @@
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+use crate::storage::registry::DEFAULT_DATABASE;
+
+/// Name of the query parameter that chooses the database of a request.
+pub const DATABASE_QUERY_PARAM: &str = "db";
+/// Name of the header that chooses the database of a request.
+pub const DATABASE_HEADER: &str = "x-database";
+
+/// The name of the database a request is served against.
+///
+/// The database is chosen by the `db` query parameter, then by the `x-database` header, and is
+/// the default database if the request chooses neither.
+pub struct DatabaseSelector(pub String);
+
+impl FromRequest<'_, '_> for DatabaseSelector {
+    type Error = ();
+
+    fn from_request(request: &Request<'_>) -> Outcome<Self, Self::Error> {
+        if let Some(Ok(name)) = request.get_query_value::<String>(DATABASE_QUERY_PARAM) {
+            return Outcome::Success(DatabaseSelector(name));
+        }
+
+        let keys: Vec<_> = request.headers().get(DATABASE_HEADER).collect();
+        if keys.len() == 1 {
+            return Outcome::Success(DatabaseSelector(keys[0].to_string()));
+        }
+
+        Outcome::Success(DatabaseSelector(DEFAULT_DATABASE.to_string()))
+    }
+}
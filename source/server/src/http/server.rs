@@ -1,22 +1,40 @@
-use std::sync::Mutex;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
 
+use chrono::Utc;
 use rocket::config::{Environment, LoggingLevel};
 use rocket::http::Status;
+use rocket::request::LenientForm;
 use rocket::{Config, Response, State};
 use rocket_contrib::json::Json;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 
+use crate::api::collection_action::{ActionError, ActionRegistry};
 use crate::io::logger::s_log;
 use crate::io::logger::EventCategory::Network;
 use crate::io::logger::EventSeverity::Info;
+use crate::lib::json::schema::SchemaViolation;
 use crate::lib::json::types::{JsonObject, SmartJson};
 use crate::lib::response_builder;
 use crate::lib::response_builder::ResponseFormat;
 use crate::lib::response_builder::ResponseFormat::JSON;
-use crate::storage::database::Database;
+use crate::page::error::WriteError;
+use crate::page::record_id::RecordId;
+use crate::page::wal;
+use crate::storage::compaction;
+use crate::storage::database::DatabaseError;
+use crate::storage::history;
+use crate::storage::options::CollectionOptions;
+use crate::storage::registry::{DatabaseRegistry, RegistryError};
+use crate::storage::tail;
+use crate::storage::tail::{TailOutput, DEFAULT_TAIL_LIMIT, DEFAULT_TAIL_TIMEOUT};
+use crate::storage::ttl;
+use crate::storage::view::{ViewDefinition, ViewEntry};
 
 use super::config::HttpServerConfig;
+use super::database_selector::DatabaseSelector;
 
 /// An IrisDB HTTP server.
 pub struct HttpServer {
@@ -28,8 +46,10 @@ impl HttpServer {
         HttpServer { cfg }
     }
 
-    /// Starts the HTTP server.
-    pub fn start(&self) {
+    /// Starts the HTTP server, serving requests against the databases of the registry. The
+    /// compaction worker, the TTL reaper, the history pruner and the write-ahead log flusher are
+    /// started alongside the server.
+    pub fn start(&self, registry: DatabaseRegistry) {
         let HttpServerConfig { port } = self.cfg;
 
         let config = Config::build(Environment::Staging)
@@ -44,9 +64,41 @@ impl HttpServer {
             &*format!("HTTP protocol started on http://localhost:{}", port),
         );
 
+        wal::spawn_flusher(registry.wal().clone());
+
+        let registry = Arc::new(registry);
+        compaction::spawn_worker(registry.clone());
+        ttl::spawn_reaper(registry.clone());
+        history::spawn_pruner(registry.clone());
+
         rocket::custom(config)
-            .mount("/", routes![graph_query])
-            .manage(Mutex::new(Database::new()))
+            .mount(
+                "/",
+                routes![
+                    graph_query,
+                    dispatch_action,
+                    tail_collection,
+                    read_documents,
+                    document_history,
+                    scrub_collection,
+                    compact_collection,
+                    compaction_progress,
+                    database_stats,
+                    collection_stats,
+                    list_collections,
+                    create_collection,
+                    drop_collection,
+                    rename_collection,
+                    list_views,
+                    create_view,
+                    drop_view,
+                    list_databases,
+                    create_database,
+                    drop_database
+                ],
+            )
+            .manage(registry)
+            .manage(ActionRegistry::builtin())
             .launch();
     }
 }
@@ -59,13 +111,24 @@ struct RequestBody {
     return_stmt: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct CreateCollectionBody {
+    #[serde(default)]
+    options: CollectionOptions,
+}
+
+#[derive(Deserialize)]
+struct RenameCollectionBody {
+    name: String,
+}
+
 #[post("/collection/_query", data = "<body>")]
 fn graph_query<'a>(
     body: Json<JsonObject>,
-    ctx: State<Mutex<Database>>,
+    ctx: State<Arc<DatabaseRegistry>>,
     rf: ResponseFormat,
 ) -> Response<'a> {
-    let mut db = ctx.inner().lock().unwrap();
+    let db = ctx.inner();
 
     let body = Value::from(body.into_inner());
 
@@ -73,3 +136,780 @@ fn graph_query<'a>(
 
     response_builder::new_response(JSON, Status::Ok, "{}\n".to_string())
 }
+
+/// Dispatches a collection action by name, such as `insert`. The request body is deserialized
+/// into the input of the action, and the output of the action is the response. Read actions such
+/// as `find` can also be dispatched on a view.
+#[post("/<collection>/<action>", data = "<body>")]
+fn dispatch_action<'a>(
+    collection: String,
+    action: String,
+    body: Json<JsonObject>,
+    database: DatabaseSelector,
+    actions: State<ActionRegistry>,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    if !actions.contains(&action) {
+        let mut names = actions.names();
+        names.sort();
+        let data = json!({ "action": action, "actions": names });
+
+        return response_builder::serialize(
+            rf,
+            Status::NotFound,
+            &response_builder::json_error_object("Action not found", data.as_object().unwrap()),
+        );
+    }
+
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    let input = Value::from(body.into_inner());
+    let (c, view) = match db.collection(&collection) {
+        Some(c) => (c, None),
+        None => match db.view(&collection) {
+            Some(_) if !actions.is_read(&action) => return view_read_only(rf, &collection),
+            Some(view) => match db.collection(&view.definition.source) {
+                Some(c) => (c, Some(view)),
+                None => return view_source_not_found(rf, &view),
+            },
+            None => return collection_not_found(rf, &collection),
+        },
+    };
+
+    if actions.is_read(&action) {
+        let view = view.as_ref().map(|view| &view.definition);
+        let result = actions.dispatch_read(&action, input, &c.read().unwrap(), view);
+        return action_response(rf, &collection, result);
+    }
+
+    let result = actions.dispatch(&action, input, &mut c.write().unwrap());
+    let result = result
+        .and_then(|output| {
+            ctx.balance_pool()
+                .map(|_| output)
+                .map_err(ActionError::Write)
+        })
+        .and_then(|output| {
+            db.update_catalog(&collection)
+                .map(|_| output)
+                .map_err(|e| ActionError::Write(WriteError::Io(e)))
+        });
+
+    action_response(rf, &collection, result)
+}
+
+/// The response to a dispatched collection action.
+fn action_response<'a>(
+    rf: ResponseFormat,
+    collection: &str,
+    result: Result<Value, ActionError>,
+) -> Response<'a> {
+    match result {
+        Ok(output) => response_builder::serialize(rf, Status::Ok, &output),
+        Err(ActionError::Input(e)) => invalid_body(rf, e.path, e.msg),
+        Err(ActionError::Write(e)) => write_error(rf, collection, e),
+        Err(ActionError::NotFound(_)) => unreachable!("the action was looked up before"),
+    }
+}
+
+/// Reads the documents inserted into a capped collection after a record id, waiting up to
+/// `timeout` milliseconds for new documents if there are none yet.
+#[get("/<collection>/tail?<after>&<limit>&<timeout>")]
+fn tail_collection<'a>(
+    collection: String,
+    after: Option<String>,
+    limit: Option<usize>,
+    timeout: Option<u64>,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    let c = match db.collection(&collection) {
+        Some(c) => c,
+        None => return collection_not_found(rf, &collection),
+    };
+
+    let after = match after.map(|after| after.parse::<RecordId>()) {
+        Some(Ok(id)) => Some(id),
+        Some(Err(())) => {
+            return invalid_query(rf, "after", "expected a record id formatted as page:slot")
+        }
+        None => None,
+    };
+
+    if c.read().unwrap().options().capped.is_none() {
+        let data = json!({ "collection": collection });
+
+        return response_builder::serialize(
+            rf,
+            Status::BadRequest,
+            &response_builder::json_error_object(
+                "Collection is not capped",
+                data.as_object().unwrap(),
+            ),
+        );
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_TAIL_LIMIT);
+    let timeout = timeout
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_TAIL_TIMEOUT);
+
+    match tail::tail(&c, after, limit, timeout) {
+        Ok(documents) => {
+            response_builder::serialize(rf, Status::Ok, &TailOutput::new(documents, after))
+        }
+        Err(e) => read_error(rf, &collection, e),
+    }
+}
+
+#[derive(FromForm)]
+struct ReadQuery {
+    #[form(field = "asOf")]
+    as_of: Option<String>,
+}
+
+/// Reads every document of a collection or view. With `asOf`, either an RFC 3339 date or
+/// milliseconds since the unix epoch, the documents are read from the history of the collection
+/// as they were at that time. A view runs its query over the documents of its source collection.
+#[get("/<collection>/documents?<query..>")]
+fn read_documents<'a>(
+    collection: String,
+    query: LenientForm<ReadQuery>,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    let (c, view) = match db.collection(&collection) {
+        Some(c) => (c, None),
+        None => match db.view(&collection) {
+            Some(view) => match db.collection(&view.definition.source) {
+                Some(c) => (c, Some(view)),
+                None => return view_source_not_found(rf, &view),
+            },
+            None => return collection_not_found(rf, &collection),
+        },
+    };
+
+    let as_of = match query
+        .into_inner()
+        .as_of
+        .map(|as_of| history::parse_as_of(&as_of))
+    {
+        Some(Some(as_of)) => Some(as_of),
+        Some(None) => {
+            return invalid_query(
+                rf,
+                "asOf",
+                "expected an RFC 3339 date or milliseconds since the unix epoch",
+            )
+        }
+        None => None,
+    };
+
+    let c = c.read().unwrap();
+    let source = c.name().original();
+    let documents = match as_of {
+        Some(as_of) => {
+            let history = match c.history() {
+                Some(history) => history,
+                None => return no_history(rf, source),
+            };
+
+            if as_of < history.horizon(Utc::now()) {
+                let data = json!({ "collection": source, "asOf": as_of.to_rfc3339() });
+
+                return response_builder::serialize(
+                    rf,
+                    Status::BadRequest,
+                    &response_builder::json_error_object(
+                        "asOf is older than the history retention",
+                        data.as_object().unwrap(),
+                    ),
+                );
+            }
+
+            history.documents_as_of(as_of)
+        }
+        None => c.pages().documents().map(|documents| {
+            documents
+                .into_iter()
+                .map(|(_, document)| document.as_json().clone())
+                .collect()
+        }),
+    };
+
+    let documents = match (documents, view) {
+        (Ok(documents), Some(view)) => view.definition.run(documents),
+        (Ok(documents), None) => documents,
+        (Err(e), _) => return read_error(rf, source, e),
+    };
+
+    response_builder::serialize(rf, Status::Ok, &json!({ "documents": documents }))
+}
+
+/// Reads every kept version of a document, oldest first. The `id` is read as JSON, or as a
+/// string if it is not valid JSON.
+#[get("/<collection>/history?<id>")]
+fn document_history<'a>(
+    collection: String,
+    id: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    let c = match db.collection(&collection) {
+        Some(c) => c,
+        None => return collection_not_found(rf, &collection),
+    };
+
+    let id = serde_json::from_str(&id).unwrap_or(Value::String(id));
+
+    let c = c.read().unwrap();
+    let history = match c.history() {
+        Some(history) => history,
+        None => return no_history(rf, &collection),
+    };
+
+    match history.versions_of(&id) {
+        Ok(versions) => {
+            response_builder::serialize(rf, Status::Ok, &json!({ "id": id, "versions": versions }))
+        }
+        Err(e) => read_error(rf, &collection, e),
+    }
+}
+
+/// Verifies every page of a collection against its checksums.
+#[post("/_admin/scrub/<collection>")]
+fn scrub_collection<'a>(
+    collection: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.collection(&collection) {
+        Some(c) => response_builder::serialize(rf, Status::Ok, &c.read().unwrap().scrub()),
+        None => collection_not_found(rf, &collection),
+    }
+}
+
+/// Starts compacting a collection in the background, responding with the compaction progress.
+#[post("/_admin/compact/<collection>")]
+fn compact_collection<'a>(
+    collection: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.collection(&collection) {
+        Some(c) => {
+            let mut c = c.write().unwrap();
+            let status = if c.start_compaction() {
+                Status::Accepted
+            } else {
+                Status::Ok
+            };
+
+            response_builder::serialize(rf, status, c.compaction())
+        }
+        None => collection_not_found(rf, &collection),
+    }
+}
+
+/// Progress of the current or last compaction of a collection.
+#[get("/_admin/compact/<collection>")]
+fn compaction_progress<'a>(
+    collection: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.collection(&collection) {
+        Some(c) => response_builder::serialize(rf, Status::Ok, c.read().unwrap().compaction()),
+        None => collection_not_found(rf, &collection),
+    }
+}
+
+/// Every collection with basic statistics.
+#[get("/_admin/collections")]
+fn list_collections<'a>(
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    response_builder::serialize(rf, Status::Ok, &db.list_collections())
+}
+
+/// Creates a collection with the options in the request body.
+#[post("/_admin/collections/<collection>", data = "<body>")]
+fn create_collection<'a>(
+    collection: String,
+    body: Json<JsonObject>,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let body: CreateCollectionBody =
+        match SmartJson::from(Value::from(body.into_inner())).into_struct() {
+            Ok(body) => body,
+            Err(e) => return invalid_body(rf, e.path, e.msg),
+        };
+
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.create_collection(&collection, body.options) {
+        Ok(_) => response_builder::serialize(rf, Status::Created, &json!({ "name": collection })),
+        Err(e) => database_error(rf, e),
+    }
+}
+
+/// Drops a collection along with its data.
+#[delete("/_admin/collections/<collection>")]
+fn drop_collection<'a>(
+    collection: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.drop_collection(&collection) {
+        Ok(()) => response_builder::serialize(rf, Status::Ok, &json!({ "name": collection })),
+        Err(e) => database_error(rf, e),
+    }
+}
+
+/// Renames a collection to the name in the request body.
+#[post("/_admin/collections/<collection>/rename", data = "<body>")]
+fn rename_collection<'a>(
+    collection: String,
+    body: Json<JsonObject>,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let body: RenameCollectionBody =
+        match SmartJson::from(Value::from(body.into_inner())).into_struct() {
+            Ok(body) => body,
+            Err(e) => return invalid_body(rf, e.path, e.msg),
+        };
+
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.rename_collection(&collection, &body.name) {
+        Ok(_) => response_builder::serialize(rf, Status::Ok, &json!({ "name": body.name })),
+        Err(e) => database_error(rf, e),
+    }
+}
+
+/// Storage statistics of every collection of a database.
+#[get("/_admin/stats")]
+fn database_stats<'a>(
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.collection_stats() {
+        Ok(stats) => response_builder::serialize(rf, Status::Ok, &stats),
+        Err(e) => stats_error(rf, e),
+    }
+}
+
+/// Storage statistics of a collection.
+#[get("/_admin/stats/<collection>")]
+fn collection_stats<'a>(
+    collection: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    let stats = match db.collection(&collection) {
+        Some(c) => c.read().unwrap().stats(),
+        None => return collection_not_found(rf, &collection),
+    };
+
+    match stats {
+        Ok(stats) => response_builder::serialize(rf, Status::Ok, &stats),
+        Err(e) => stats_error(rf, e),
+    }
+}
+
+/// Lists the views of a database.
+#[get("/_admin/views")]
+fn list_views<'a>(
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    response_builder::serialize(rf, Status::Ok, &db.views())
+}
+
+/// Saves a query over a collection as a view.
+#[post("/_admin/views/<view>", data = "<body>")]
+fn create_view<'a>(
+    view: String,
+    body: Json<JsonObject>,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let definition: ViewDefinition =
+        match SmartJson::from(Value::from(body.into_inner())).into_struct() {
+            Ok(definition) => definition,
+            Err(e) => return invalid_body(rf, e.path, e.msg),
+        };
+
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.create_view(&view, definition) {
+        Ok(()) => response_builder::serialize(rf, Status::Created, &json!({ "name": view })),
+        Err(e) => database_error(rf, e),
+    }
+}
+
+/// Removes a view, leaving its source collection untouched.
+#[delete("/_admin/views/<view>")]
+fn drop_view<'a>(
+    view: String,
+    database: DatabaseSelector,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    let db = match ctx.get(&database.0) {
+        Some(db) => db,
+        None => return database_not_found(rf, &database.0),
+    };
+
+    match db.drop_view(&view) {
+        Ok(()) => response_builder::serialize(rf, Status::Ok, &json!({ "name": view })),
+        Err(e) => database_error(rf, e),
+    }
+}
+
+/// Every database with basic statistics.
+#[get("/_admin/databases")]
+fn list_databases<'a>(ctx: State<Arc<DatabaseRegistry>>, rf: ResponseFormat) -> Response<'a> {
+    response_builder::serialize(rf, Status::Ok, &ctx.list())
+}
+
+/// Creates a database without any collections.
+#[post("/_admin/databases/<name>")]
+fn create_database<'a>(
+    name: String,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    match ctx.create_database(&name) {
+        Ok(_) => response_builder::serialize(rf, Status::Created, &json!({ "name": name })),
+        Err(e) => registry_error(rf, e),
+    }
+}
+
+/// Drops a database along with every collection in it.
+#[delete("/_admin/databases/<name>")]
+fn drop_database<'a>(
+    name: String,
+    ctx: State<Arc<DatabaseRegistry>>,
+    rf: ResponseFormat,
+) -> Response<'a> {
+    match ctx.drop_database(&name) {
+        Ok(()) => response_builder::serialize(rf, Status::Ok, &json!({ "name": name })),
+        Err(e) => registry_error(rf, e),
+    }
+}
+
+/// Builds the response for a write that failed.
+fn write_error<'a>(rf: ResponseFormat, collection: &str, e: WriteError) -> Response<'a> {
+    let (status, msg, data) = match e {
+        WriteError::SchemaViolation(violation) => {
+            return schema_violation(rf, collection, violation)
+        }
+        WriteError::DuplicateId(id) => (
+            Status::Conflict,
+            "Duplicate _id",
+            json!({ "collection": collection, "id": id }),
+        ),
+        WriteError::InvalidId(id) => (
+            Status::BadRequest,
+            "An _id cannot be null or an array",
+            json!({ "collection": collection, "id": id }),
+        ),
+        WriteError::InvalidUpdate(reason) => (
+            Status::BadRequest,
+            "Invalid update",
+            json!({ "collection": collection, "reason": reason }),
+        ),
+        WriteError::CappedCollection(_) => (
+            Status::BadRequest,
+            "Documents of a capped collection cannot be moved or removed",
+            json!({ "collection": collection }),
+        ),
+        e => (
+            Status::InternalServerError,
+            "Write failed",
+            json!({ "collection": collection, "reason": format!("{:?}", e) }),
+        ),
+    };
+
+    response_builder::serialize(
+        rf,
+        status,
+        &response_builder::json_error_object(msg, data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a read that failed.
+fn read_error<'a>(rf: ResponseFormat, collection: &str, e: WriteError) -> Response<'a> {
+    let data = json!({ "collection": collection, "reason": format!("{:?}", e) });
+
+    response_builder::serialize(
+        rf,
+        Status::InternalServerError,
+        &response_builder::json_error_object("Read failed", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a history read of a collection that keeps no history.
+fn no_history<'a>(rf: ResponseFormat, collection: &str) -> Response<'a> {
+    let data = json!({ "collection": collection });
+
+    response_builder::serialize(
+        rf,
+        Status::BadRequest,
+        &response_builder::json_error_object(
+            "Collection does not keep a history",
+            data.as_object().unwrap(),
+        ),
+    )
+}
+
+/// Builds the response for statistics that could not be read from the filesystem.
+fn stats_error<'a>(rf: ResponseFormat, e: io::Error) -> Response<'a> {
+    let data = json!({ "reason": format!("{:?}", e) });
+
+    response_builder::serialize(
+        rf,
+        Status::InternalServerError,
+        &response_builder::json_error_object("Statistics failed", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a write to a view.
+fn view_read_only<'a>(rf: ResponseFormat, view: &str) -> Response<'a> {
+    let data = json!({ "view": view });
+
+    response_builder::serialize(
+        rf,
+        Status::MethodNotAllowed,
+        &response_builder::json_error_object("Views are read-only", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a read of a view whose source collection no longer exists.
+fn view_source_not_found<'a>(rf: ResponseFormat, view: &ViewEntry) -> Response<'a> {
+    let data = json!({ "view": view.name, "source": view.definition.source });
+
+    response_builder::serialize(
+        rf,
+        Status::NotFound,
+        &response_builder::json_error_object("View source not found", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a request body that could not be deserialized.
+fn invalid_body<'a>(rf: ResponseFormat, path: String, reason: String) -> Response<'a> {
+    let data = json!({ "path": path, "reason": reason });
+
+    response_builder::serialize(
+        rf,
+        Status::BadRequest,
+        &response_builder::json_error_object("Invalid request body", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a query parameter that could not be parsed.
+fn invalid_query<'a>(rf: ResponseFormat, param: &str, reason: &str) -> Response<'a> {
+    let data = json!({ "param": param, "reason": reason });
+
+    response_builder::serialize(
+        rf,
+        Status::BadRequest,
+        &response_builder::json_error_object("Invalid query parameter", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a document that does not match the schema of its collection.
+fn schema_violation<'a>(
+    rf: ResponseFormat,
+    collection: &str,
+    violation: SchemaViolation,
+) -> Response<'a> {
+    let data = json!({
+        "collection": collection,
+        "path": violation.path,
+        "reason": violation.msg,
+    });
+
+    response_builder::serialize(
+        rf,
+        Status::BadRequest,
+        &response_builder::json_error_object("Schema validation failed", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a failed change to the collections of a database.
+fn database_error<'a>(rf: ResponseFormat, e: DatabaseError) -> Response<'a> {
+    let (status, msg, data) = match e {
+        DatabaseError::InvalidName(reason) => (
+            Status::BadRequest,
+            "Invalid collection name",
+            json!({ "reason": format!("{:?}", reason) }),
+        ),
+        DatabaseError::InvalidOptions(reason) => (
+            Status::BadRequest,
+            "Invalid collection options",
+            json!({ "reason": reason }),
+        ),
+        DatabaseError::AlreadyExists(name) => (
+            Status::Conflict,
+            "Collection already exists",
+            json!({ "collection": name }),
+        ),
+        DatabaseError::NotFound(name) => return collection_not_found(rf, &name),
+        DatabaseError::ViewNotFound(name) => {
+            (Status::NotFound, "View not found", json!({ "view": name }))
+        }
+        e => (
+            Status::InternalServerError,
+            "Collection change failed",
+            json!({ "reason": format!("{:?}", e) }),
+        ),
+    };
+
+    response_builder::serialize(
+        rf,
+        status,
+        &response_builder::json_error_object(msg, data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a failed change to the databases of the server.
+fn registry_error<'a>(rf: ResponseFormat, e: RegistryError) -> Response<'a> {
+    let (status, msg, data) = match e {
+        RegistryError::InvalidName(reason) => (
+            Status::BadRequest,
+            "Invalid database name",
+            json!({ "reason": format!("{:?}", reason) }),
+        ),
+        RegistryError::AlreadyExists(name) => (
+            Status::Conflict,
+            "Database already exists",
+            json!({ "database": name }),
+        ),
+        RegistryError::NotFound(name) => return database_not_found(rf, &name),
+        RegistryError::DefaultDatabase => (
+            Status::BadRequest,
+            "The default database cannot be dropped",
+            json!({}),
+        ),
+        e => (
+            Status::InternalServerError,
+            "Database change failed",
+            json!({ "reason": format!("{:?}", e) }),
+        ),
+    };
+
+    response_builder::serialize(
+        rf,
+        status,
+        &response_builder::json_error_object(msg, data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a request to a database that does not exist.
+fn database_not_found<'a>(rf: ResponseFormat, database: &str) -> Response<'a> {
+    let data = json!({ "database": database });
+
+    response_builder::serialize(
+        rf,
+        Status::NotFound,
+        &response_builder::json_error_object("Database not found", data.as_object().unwrap()),
+    )
+}
+
+/// Builds the response for a request to a collection that does not exist.
+fn collection_not_found<'a>(rf: ResponseFormat, collection: &str) -> Response<'a> {
+    let data = json!({ "collection": collection });
+
+    response_builder::serialize(
+        rf,
+        Status::NotFound,
+        &response_builder::json_error_object("Collection not found", data.as_object().unwrap()),
+    )
+}
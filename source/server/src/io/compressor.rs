@@ -1,27 +1,58 @@
+use std::io;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
 use crate::io::compressor::compressors::{Snappy, ZLib};
 
 /// An algorithm for compressing a vector of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CompressionStrategy {
     Snappy,
     ZLib,
 }
 
 impl CompressionStrategy {
-    fn compress(&self, buf: &Vec<u8>) -> Vec<u8> {
-        let compressor: &dyn Compressor = match self {
+    pub fn compress(&self, buf: &Vec<u8>) -> Vec<u8> {
+        self.compressor().compress(buf)
+    }
+
+    pub fn decompress(&self, buf: &Vec<u8>) -> io::Result<Vec<u8>> {
+        self.compressor().decompress(buf)
+    }
+
+    /// The name of the algorithm.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CompressionStrategy::Snappy => "snappy",
+            CompressionStrategy::ZLib => "zlib",
+        }
+    }
+
+    fn compressor(&self) -> &'static dyn Compressor {
+        match self {
             CompressionStrategy::Snappy => &Snappy,
             CompressionStrategy::ZLib => &ZLib,
-        };
+        }
+    }
+}
 
-        let out = compressor.compress(buf);
+impl FromStr for CompressionStrategy {
+    type Err = ();
 
-        out
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "snappy" => Ok(CompressionStrategy::Snappy),
+            "zlib" => Ok(CompressionStrategy::ZLib),
+            _ => Err(()),
+        };
     }
 }
 
 trait Compressor {
     fn compress(&self, input: &Vec<u8>) -> Vec<u8>;
-    fn decompress(&self, input: &Vec<u8>) -> Vec<u8>;
+    fn decompress(&self, input: &Vec<u8>) -> io::Result<Vec<u8>>;
 }
 
 mod compressors {
@@ -44,11 +75,11 @@ mod compressors {
             wtr.into_inner().unwrap()
         }
 
-        fn decompress(&self, input: &Vec<u8>) -> Vec<u8> {
+        fn decompress(&self, input: &Vec<u8>) -> io::Result<Vec<u8>> {
             let mut buf = Vec::new();
-            snap::read::FrameDecoder::new(input.as_slice()).read_to_end(&mut buf);
+            snap::read::FrameDecoder::new(input.as_slice()).read_to_end(&mut buf)?;
 
-            buf
+            Ok(buf)
         }
     }
 
@@ -61,13 +92,13 @@ mod compressors {
             e.finish().unwrap()
         }
 
-        fn decompress(&self, input: &Vec<u8>) -> Vec<u8> {
+        fn decompress(&self, input: &Vec<u8>) -> io::Result<Vec<u8>> {
             let mut e = ZlibDecoder::new(Cursor::new(input));
 
             let mut out = Vec::new();
-            e.read_to_end(&mut out);
+            e.read_to_end(&mut out)?;
 
-            out
+            Ok(out)
         }
     }
 
@@ -104,7 +135,7 @@ mod compressors {
 
             let out = Snappy.compress(&bytes);
 
-            assert_eq!(bytes, Snappy.decompress(&out));
+            assert_eq!(bytes, Snappy.decompress(&out).unwrap());
         }
 
         #[test]
@@ -129,7 +160,7 @@ mod compressors {
 
             let out = ZLib.compress(&bytes);
 
-            assert_eq!(bytes, ZLib.decompress(&out));
+            assert_eq!(bytes, ZLib.decompress(&out).unwrap());
         }
     }
 }
@@ -19,11 +19,33 @@ pub trait Filesystem {
     /// Check if a file exists.
     fn file_exists(&self, f: &FileDescriptor) -> bool;
 
+    /// Reads the entire contents of a file.
+    fn read(&self, f: &FileDescriptor) -> io::Result<Vec<u8>>;
+
+    /// The size of a file in bytes.
+    fn file_size(&self, f: &FileDescriptor) -> io::Result<u64>;
+
+    /// Lists the names of every file in a directory. The descriptor name is a subdirectory, or
+    /// empty for the directory itself.
+    fn list_files(&self, dir: &FileDescriptor) -> io::Result<Vec<String>>;
+
     /// Overwrites a file with the new contents.
     fn overwrite(&self, f: &FileDescriptor, bytes: Vec<u8>) -> io::Result<()>;
 
     /// Appends bytes to the end of a file.
     fn append(&self, f: &FileDescriptor, bytes: Vec<u8>) -> io::Result<()>;
+
+    /// Flushes the contents of a file to the storage device.
+    fn sync(&self, f: &FileDescriptor) -> io::Result<()>;
+
+    /// Removes a file if it exists.
+    fn remove(&self, f: &FileDescriptor) -> io::Result<()>;
+
+    /// Moves a file to a new name, replacing any file with that name.
+    fn rename(&self, from: &FileDescriptor, to: &FileDescriptor) -> io::Result<()>;
+
+    /// Removes an empty directory if it exists.
+    fn remove_dir(&self, f: &FileDescriptor) -> io::Result<()>;
 }
 
 /// The native filesystem used in the production build.
@@ -47,6 +69,27 @@ impl Filesystem for StdFs {
         Path::new(&f.relative_path()).exists()
     }
 
+    fn read(&self, f: &FileDescriptor) -> io::Result<Vec<u8>> {
+        fs::read(&f.relative_path())
+    }
+
+    fn file_size(&self, f: &FileDescriptor) -> io::Result<u64> {
+        Ok(fs::metadata(&f.relative_path())?.len())
+    }
+
+    fn list_files(&self, dir: &FileDescriptor) -> io::Result<Vec<String>> {
+        let mut names = Vec::new();
+
+        for entry in fs::read_dir(dir.relative_path())? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                names.push(entry.file_name().to_string_lossy().into_owned());
+            }
+        }
+
+        Ok(names)
+    }
+
     fn overwrite(&self, f: &FileDescriptor, bytes: Vec<u8>) -> io::Result<()> {
         fs::write(&f.relative_path(), bytes)?;
         Ok(())
@@ -59,10 +102,35 @@ impl Filesystem for StdFs {
             .create(true)
             .open(&f.relative_path())?;
 
-        file.write(&*bytes)?;
+        file.write_all(&*bytes)?;
 
         Ok(())
     }
+
+    fn sync(&self, f: &FileDescriptor) -> io::Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .open(&f.relative_path())?
+            .sync_all()
+    }
+
+    fn remove(&self, f: &FileDescriptor) -> io::Result<()> {
+        match fs::remove_file(&f.relative_path()) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    fn rename(&self, from: &FileDescriptor, to: &FileDescriptor) -> io::Result<()> {
+        fs::rename(&from.relative_path(), &to.relative_path())
+    }
+
+    fn remove_dir(&self, f: &FileDescriptor) -> io::Result<()> {
+        match fs::remove_dir(&f.relative_path()) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
 }
 
 /// <strong>SHOULD ALWAYS BE USED WHEN TESTING</strong>
@@ -86,6 +154,18 @@ impl Filesystem for InactiveFs {
         true
     }
 
+    fn read(&self, _f: &FileDescriptor) -> io::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    fn file_size(&self, _f: &FileDescriptor) -> io::Result<u64> {
+        Ok(0)
+    }
+
+    fn list_files(&self, _dir: &FileDescriptor) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
     fn overwrite(&self, _f: &FileDescriptor, _bytes: Vec<u8>) -> io::Result<()> {
         Ok(())
     }
@@ -93,4 +173,20 @@ impl Filesystem for InactiveFs {
     fn append(&self, _f: &FileDescriptor, _bytes: Vec<u8>) -> io::Result<()> {
         Ok(())
     }
+
+    fn sync(&self, _f: &FileDescriptor) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove(&self, _f: &FileDescriptor) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn rename(&self, _from: &FileDescriptor, _to: &FileDescriptor) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn remove_dir(&self, _f: &FileDescriptor) -> io::Result<()> {
+        Ok(())
+    }
 }
@@ -51,6 +51,8 @@ pub enum EventCategory {
     Filesystem,
     /// Connection pool event.
     ConnPool,
+    /// Background job event.
+    Background,
 }
 
 const LARGEST_CATEGORY_LEN: u32 = 10;
@@ -62,6 +64,7 @@ impl ToString for EventCategory {
             EventCategory::Network => "NETWORK",
             EventCategory::Filesystem => "FILESYSTEM",
             EventCategory::ConnPool => "CONNPOOL",
+            EventCategory::Background => "BACKGROUND",
         }
         .to_string();
     }
@@ -8,10 +8,14 @@ extern crate rocket;
 
 use crate::http::{config::HttpServerConfig, server::HttpServer};
 use crate::io::logger::s_log;
-use crate::io::logger::EventSeverity::Info;
+use crate::io::logger::EventSeverity::{Fatal, Info, Warn};
 use crate::io::path;
 use crate::io::path::DatabasePath;
+use crate::page::buffer_pool::DEFAULT_BUFFER_POOL_SIZE;
+use crate::page::wal::{WriteAheadLog, DEFAULT_BATCH_INTERVAL, DEFAULT_CHECKPOINT_SIZE};
+use crate::storage::registry::DatabaseRegistry;
 use std::env;
+use std::time::Duration;
 
 mod api;
 mod http;
@@ -56,6 +60,75 @@ fn main() {
         ),
     );
 
+    let buffer_pool_size = env::var("IRIS_BUFFER_POOL_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_BUFFER_POOL_SIZE);
+
+    s_log(
+        Info,
+        General,
+        &*format!("[BufferPool-Size] {} bytes", buffer_pool_size),
+    );
+
+    let checkpoint_size = env::var("IRIS_WAL_CHECKPOINT_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_CHECKPOINT_SIZE);
+
+    let batch_interval = env::var("IRIS_WAL_BATCH_INTERVAL")
+        .ok()
+        .and_then(|ms| ms.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_BATCH_INTERVAL);
+
+    let wal = WriteAheadLog::new(checkpoint_size, batch_interval);
+
+    match wal.recover() {
+        Ok(recovery) => {
+            s_log(
+                Info,
+                Filesystem,
+                &*format!("[WAL-Recovery] replayed {} records", recovery.records),
+            );
+
+            if recovery.discarded > 0 {
+                s_log(
+                    Warn,
+                    Filesystem,
+                    &*format!(
+                        "[WAL-Recovery] discarded {} bytes of an incomplete record",
+                        recovery.discarded
+                    ),
+                );
+            }
+        }
+        Err(e) => {
+            s_log(Fatal, Filesystem, &*format!("[WAL-Recovery] {}", e));
+            panic!("{}", e);
+        }
+    }
+
+    let registry = match DatabaseRegistry::open(buffer_pool_size, wal) {
+        Ok(registry) => registry,
+        Err(e) => {
+            s_log(Fatal, Filesystem, &*format!("[Catalog-Load] {:?}", e));
+            panic!("{:?}", e);
+        }
+    };
+
+    for db in registry.databases() {
+        s_log(
+            Info,
+            Filesystem,
+            &*format!(
+                "[Catalog-Load] database={} collections={}",
+                db.name(),
+                db.collections().len()
+            ),
+        );
+    }
+
     let s = HttpServer::new(HttpServerConfig { port: 12712 });
-    s.start();
+    s.start(registry);
 }
@@ -60,8 +60,8 @@ pub mod encoder {
     /// Retruns a closure that accumulates all BSON documents from a document iterator.
     fn accumulate_bson_documents() -> Box<dyn Fn(Vec<u8>, Vec<u8>) -> Vec<u8>> {
         Box::new(|mut pv, mut cv| {
-            cv.append(&mut pv);
-            return cv;
+            pv.append(&mut cv);
+            return pv;
         })
     }
 
@@ -131,7 +131,7 @@ pub mod decoder {
         let mut acc: Vec<Document> = Vec::new();
 
         loop {
-            if (cursor.position() as usize) >= documents.len() - 1 {
+            if (cursor.position() as usize) >= documents.len() {
                 break;
             }
 
@@ -208,4 +208,33 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_encode_decode_preserves_order() -> Result<(), ReadError> {
+        let positions = vec![
+            Position { x: 1, y: 1 },
+            Position { x: 2, y: 2 },
+            Position { x: 3, y: 3 },
+        ];
+
+        let decode_result = decode_documents::<Position>(encode_structs(positions))?;
+        assert_eq!(
+            decode_result,
+            vec![
+                Position { x: 1, y: 1 },
+                Position { x: 2, y: 2 },
+                Position { x: 3, y: 3 },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_empty() -> Result<(), ReadError> {
+        let decode_result = decode_documents::<Position>(Vec::new())?;
+        assert!(decode_result.is_empty());
+
+        Ok(())
+    }
 }
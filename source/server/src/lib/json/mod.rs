@@ -1,4 +1,5 @@
 pub mod bsonio;
 pub mod formatter;
 pub mod jsonconv;
+pub mod schema;
 pub mod types;
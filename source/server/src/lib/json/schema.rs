@@ -0,0 +1,381 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use regex::Regex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// A JSON value type that a schema can require.
+pub enum SchemaType {
+    Null,
+    Boolean,
+    Object,
+    Array,
+    Number,
+    /// A number without a fractional part.
+    Integer,
+    String,
+}
+
+impl SchemaType {
+    fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (SchemaType::Null, Value::Null) => true,
+            (SchemaType::Boolean, Value::Bool(_)) => true,
+            (SchemaType::Object, Value::Object(_)) => true,
+            (SchemaType::Array, Value::Array(_)) => true,
+            (SchemaType::Number, Value::Number(_)) => true,
+            (SchemaType::Integer, Value::Number(n)) => {
+                n.is_i64() || n.is_u64() || n.as_f64().map_or(false, |f| f.fract() == 0.0)
+            }
+            (SchemaType::String, Value::String(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+/// The `type` keyword, either a single type or a list of allowed types.
+pub enum SchemaTypes {
+    One(SchemaType),
+    Any(Vec<SchemaType>),
+}
+
+impl SchemaTypes {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            SchemaTypes::One(t) => t.matches(value),
+            SchemaTypes::Any(types) => types.iter().any(|t| t.matches(value)),
+        }
+    }
+}
+
+impl fmt::Display for SchemaTypes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let types = match self {
+            SchemaTypes::One(t) => vec![*t],
+            SchemaTypes::Any(types) => types.clone(),
+        };
+        let names: Vec<String> = types
+            .iter()
+            .map(|t| {
+                serde_json::to_value(t)
+                    .unwrap()
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+
+        write!(f, "{}", names.join(" or "))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A regular expression that is compiled when the schema is deserialized, so invalid patterns
+/// are rejected together with the rest of the schema.
+pub struct SchemaPattern(Regex);
+
+impl Serialize for SchemaPattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for SchemaPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pattern = String::deserialize(deserializer)?;
+
+        Regex::new(&pattern)
+            .map(SchemaPattern)
+            .map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+/// A subset of JSON Schema that documents can be validated against.
+///
+/// Keywords that do not apply to the type of a value are ignored, so `minimum` only constrains
+/// numbers and `pattern` only constrains strings.
+pub struct JsonSchema {
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub types: Option<SchemaTypes>,
+    /// Properties an object must have.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+    /// Schemas of the properties of an object.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, JsonSchema>,
+    /// The values a value must be one of.
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub values: Option<Vec<Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minimum: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maximum: Option<f64>,
+    /// The least amount of characters in a string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_length: Option<usize>,
+    /// The largest amount of characters in a string.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_length: Option<usize>,
+    /// A regular expression a string must contain a match of.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<SchemaPattern>,
+    /// The schema of every item of an array.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<JsonSchema>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A value that does not match a schema.
+pub struct SchemaViolation {
+    /// Path to the failing value, formatted like the path of a
+    /// [`JsonDeserializationError`](super::types::JsonDeserializationError).
+    pub path: String,
+    pub msg: String,
+}
+
+impl JsonSchema {
+    /// Validates a value against the schema, returning the first violation found.
+    pub fn validate(&self, value: &Value) -> Result<(), SchemaViolation> {
+        self.validate_at(value, &mut Vec::new())
+    }
+
+    fn validate_at(&self, value: &Value, path: &mut Vec<Segment>) -> Result<(), SchemaViolation> {
+        if let Some(types) = &self.types {
+            if !types.matches(value) {
+                return Err(violation(
+                    path,
+                    format!("expected {}, found {}", types, type_name(value)),
+                ));
+            }
+        }
+
+        if let Some(values) = &self.values {
+            if !values.contains(value) {
+                return Err(violation(path, "value is not one of the enum values"));
+            }
+        }
+
+        match value {
+            Value::Number(n) => self.validate_number(n.as_f64().unwrap_or(0.0), path),
+            Value::String(s) => self.validate_string(s, path),
+            Value::Array(items) => self.validate_array(items, path),
+            Value::Object(o) => {
+                for key in &self.required {
+                    if !o.contains_key(key) {
+                        path.push(Segment::Key(key.clone()));
+                        return Err(violation(path, "missing required property"));
+                    }
+                }
+
+                for (key, schema) in &self.properties {
+                    if let Some(value) = o.get(key) {
+                        path.push(Segment::Key(key.clone()));
+                        schema.validate_at(value, path)?;
+                        path.pop();
+                    }
+                }
+
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn validate_number(&self, n: f64, path: &[Segment]) -> Result<(), SchemaViolation> {
+        if let Some(minimum) = self.minimum {
+            if n < minimum {
+                return Err(violation(path, format!("{} is less than {}", n, minimum)));
+            }
+        }
+        if let Some(maximum) = self.maximum {
+            if n > maximum {
+                return Err(violation(
+                    path,
+                    format!("{} is greater than {}", n, maximum),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_string(&self, s: &str, path: &[Segment]) -> Result<(), SchemaViolation> {
+        let len = s.chars().count();
+
+        if let Some(min) = self.min_length {
+            if len < min {
+                return Err(violation(path, format!("shorter than {} characters", min)));
+            }
+        }
+        if let Some(max) = self.max_length {
+            if len > max {
+                return Err(violation(path, format!("longer than {} characters", max)));
+            }
+        }
+        if let Some(SchemaPattern(pattern)) = &self.pattern {
+            if !pattern.is_match(s) {
+                return Err(violation(
+                    path,
+                    format!("does not match pattern {}", pattern.as_str()),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_array(
+        &self,
+        items: &[Value],
+        path: &mut Vec<Segment>,
+    ) -> Result<(), SchemaViolation> {
+        if let Some(min) = self.min_items {
+            if items.len() < min {
+                return Err(violation(path, format!("fewer than {} items", min)));
+            }
+        }
+        if let Some(max) = self.max_items {
+            if items.len() > max {
+                return Err(violation(path, format!("more than {} items", max)));
+            }
+        }
+
+        if let Some(schema) = &self.items {
+            for (index, item) in items.iter().enumerate() {
+                path.push(Segment::Index(index));
+                schema.validate_at(item, path)?;
+                path.pop();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A step in the path to a value.
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Formats a path the way `serde_path_to_error` does: keys separated by `.`, array indices in
+/// brackets and `.` for the root value.
+fn format_path(path: &[Segment]) -> String {
+    if path.is_empty() {
+        return ".".to_string();
+    }
+
+    let mut formatted = String::new();
+    for segment in path {
+        match segment {
+            Segment::Key(key) => {
+                if !formatted.is_empty() {
+                    formatted.push('.');
+                }
+                formatted.push_str(key);
+            }
+            Segment::Index(index) => formatted.push_str(&format!("[{}]", index)),
+        }
+    }
+
+    formatted
+}
+
+fn violation<T>(path: &[Segment], msg: T) -> SchemaViolation
+where
+    T: Into<String>,
+{
+    SchemaViolation {
+        path: format_path(path),
+        msg: msg.into(),
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn schema(value: Value) -> JsonSchema {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_validate_nested_path() {
+        let schema = schema(json!({
+            "type": "object",
+            "required": ["name"],
+            "properties": {
+                "name": { "type": "string", "pattern": "^[A-Z]" },
+                "tags": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": { "score": { "type": "integer", "minimum": 0 } }
+                    }
+                },
+                "status": { "enum": ["active", "banned"] }
+            }
+        }));
+
+        assert!(schema
+            .validate(&json!({ "name": "Ann", "tags": [{ "score": 3 }], "status": "active" }))
+            .is_ok());
+
+        let err = schema
+            .validate(&json!({ "name": "Ann", "tags": [{ "score": 3 }, { "score": -1 }] }))
+            .unwrap_err();
+        assert_eq!(err.path, "tags[1].score");
+
+        let err = schema.validate(&json!({ "tags": [] })).unwrap_err();
+        assert_eq!(err.path, "name");
+
+        let err = schema.validate(&json!({ "name": "ann" })).unwrap_err();
+        assert_eq!(err.path, "name");
+
+        let err = schema
+            .validate(&json!({ "name": "Ann", "status": "deleted" }))
+            .unwrap_err();
+        assert_eq!(err.path, "status");
+
+        let err = schema.validate(&json!([])).unwrap_err();
+        assert_eq!(err.path, ".");
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        let result: Result<JsonSchema, _> = serde_json::from_value(json!({ "pattern": "(" }));
+
+        assert!(result.is_err());
+    }
+}
@@ -1,3 +1,5 @@
 pub mod json;
 pub mod response_builder;
+#[cfg(test)]
+pub mod testing;
 pub mod uid;
@@ -1,8 +1,10 @@
+use crate::lib::json::formatter;
 use crate::lib::json::types::JsonObject;
 use rocket::http::{ContentType, Status};
 use rocket::request::{FromRequest, Outcome};
 use rocket::{Request, Response};
-use serde_json::json;
+use serde::Serialize;
+use serde_json::{json, Value};
 use std::io::Cursor;
 
 pub const DEFAULT_RESPONSE_FORMAT: ResponseFormat = ResponseFormat::Table;
@@ -97,6 +99,33 @@ pub fn new_response<'a>(format: ResponseFormat, status: Status, data: String) ->
     build_response(ContentType::from(format), data, status)
 }
 
+/// Builds an API response from a serializable value. As a table, each element of an array is a
+/// row and an object is a single row.
+pub fn serialize<'a, T>(format: ResponseFormat, status: Status, value: &T) -> Response<'a>
+where
+    T: Serialize,
+{
+    let value = serde_json::to_value(value).unwrap();
+
+    let data = match format {
+        ResponseFormat::JSON => format!("{}\n", value),
+        ResponseFormat::Table => {
+            let rows: Vec<JsonObject> = match value {
+                Value::Array(items) => items
+                    .into_iter()
+                    .filter_map(|i| i.as_object().cloned())
+                    .collect(),
+                Value::Object(o) => vec![o],
+                _ => Vec::new(),
+            };
+
+            formatter::table(&rows)
+        }
+    };
+
+    new_response(format, status, data)
+}
+
 /// Builds an API error object.
 pub fn json_error_object(msg: &str, data: &JsonObject) -> JsonObject {
     json!({
@@ -0,0 +1,61 @@
+//! Helpers shared by tests.
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde_json::Value;
+
+use crate::io::path;
+use crate::lib::json::types::JsonObject;
+
+lazy_static! {
+    /// Held by a test while it uses the filesystem, since the working directory is shared by
+    /// every test of the process.
+    static ref WORKING_DIR: Mutex<()> = Mutex::new(());
+}
+
+/// Counter that gives every test directory a unique name.
+static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// Restores the working directory and removes the test directory, even if the test panics.
+struct DataDir {
+    previous: PathBuf,
+    dir: PathBuf,
+}
+
+impl Drop for DataDir {
+    fn drop(&mut self) {
+        let _ = env::set_current_dir(&self.previous);
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// The object of a JSON value, such as a document written with `json!`.
+pub fn object(value: Value) -> JsonObject {
+    value.as_object().unwrap().clone()
+}
+
+/// Runs a test in an empty database root. The test runs in a new temporary working directory,
+/// and tests that use the filesystem run one at a time.
+pub fn with_data_dir<T>(test: impl FnOnce() -> T) -> T {
+    let _lock = WORKING_DIR.lock().unwrap_or_else(|e| e.into_inner());
+
+    let dir = env::temp_dir().join(format!(
+        "iris-test-{}-{}",
+        std::process::id(),
+        DIRS.fetch_add(1, Ordering::SeqCst)
+    ));
+    fs::create_dir_all(&dir).unwrap();
+
+    let _dir = DataDir {
+        previous: env::current_dir().unwrap(),
+        dir: dir.clone(),
+    };
+    env::set_current_dir(&dir).unwrap();
+    path::prepare();
+
+    test()
+}
@@ -1,5 +1,8 @@
 //! Unique value generation.
 use std::collections::BTreeSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
 
 /// Unique value generation strategy.
 pub trait UidGenerator {
@@ -50,6 +53,29 @@ impl IntUid {
         }
     }
 
+    /// Creates a generator that starts at `pos` and never generates smaller values.
+    pub fn starting_at(pos: u64) -> IntUid {
+        IntUid {
+            pos,
+            free: BTreeSet::new(),
+        }
+    }
+
+    /// Creates a generator where the values are already in use. Unused values below the largest
+    /// used value are generated first.
+    pub fn with_used<I>(used: I) -> IntUid
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let used: BTreeSet<u64> = used.into_iter().collect();
+        let pos = used.iter().next_back().map(|uid| uid + 1).unwrap_or(0);
+
+        IntUid {
+            pos,
+            free: (0..pos).filter(|uid| !used.contains(uid)).collect(),
+        }
+    }
+
     pub fn next(&mut self) -> u64 {
         let free = &mut self.free;
 
@@ -66,14 +92,86 @@ impl IntUid {
         pos <= self.pos
     }
 
-    fn increment(&mut self) -> u64 {
+    /// Generates the uid after the largest generated uid, ignoring free uids. Starts over at 0
+    /// after the largest possible uid.
+    pub fn increment(&mut self) -> u64 {
         let pos = self.pos;
-        self.pos += 1;
+        self.pos = self.pos.wrapping_add(1);
         pos
     }
 }
 
+/// Random UUIDv4 uid, formatted as a hyphenated string.
+pub struct UuidUid;
+
+impl UidGenerator for UuidUid {
+    type Value = String;
+
+    fn generate_uid(&mut self) -> Self::Value {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// Crockford base32 alphabet used to encode sortable uids.
+const SORTABLE_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// Amount of random bits in a sortable uid.
+const SORTABLE_RANDOM_BITS: u32 = 80;
+
+/// Uid that sorts in generation order, formatted like a ULID: 26 base32 characters encoding the
+/// milliseconds since the unix epoch followed by 80 random bits.
+///
+/// Uids generated in the same millisecond increment the random bits of the previous uid, so they
+/// still sort in generation order.
+pub struct SortableUid {
+    /// Time and random bits of the last generated uid.
+    last: u128,
+}
+
+impl UidGenerator for SortableUid {
+    type Value = String;
+
+    fn generate_uid(&mut self) -> Self::Value {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let random = rand::random::<u128>() & ((1 << SORTABLE_RANDOM_BITS) - 1);
+
+        let next = (millis << SORTABLE_RANDOM_BITS) | random;
+        self.last = if next > self.last {
+            next
+        } else {
+            self.last + 1
+        };
+
+        (0..26)
+            .map(|i| SORTABLE_ALPHABET[((self.last >> (125 - 5 * i)) & 31) as usize] as char)
+            .collect()
+    }
+}
+
+impl SortableUid {
+    pub fn new() -> SortableUid {
+        SortableUid { last: 0 }
+    }
+}
+
 mod tests {
+    #[cfg(test)]
+    mod sortable {
+        use super::super::*;
+
+        #[test]
+        fn test_generation_order() {
+            let mut generator = SortableUid::new();
+
+            let uids: Vec<String> = (0..100).map(|_| generator.generate_uid()).collect();
+
+            assert!(uids.iter().all(|uid| uid.len() == 26));
+            assert!(uids.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+
     #[cfg(test)]
     mod int {
         use super::super::*;
@@ -101,5 +199,14 @@ mod tests {
             // Next free integer should be 2, not 4 because it is the smallest avaiable integer.
             assert_eq!(generator.next(), 2);
         }
+
+        #[test]
+        fn test_with_used() {
+            let mut generator = IntUid::with_used(vec![0, 1, 3]);
+
+            // The gap is filled before incrementing past the largest used integer.
+            assert_eq!(generator.next(), 2);
+            assert_eq!(generator.next(), 4);
+        }
     }
 }
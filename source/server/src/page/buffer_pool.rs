@@ -0,0 +1,139 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use serde::Serialize;
+
+/// The default amount of memory pages may occupy across all collections.
+///
+/// The standard maximum is 256MB.
+pub const DEFAULT_BUFFER_POOL_SIZE: usize = 256E6 as usize;
+
+/// Memory accounting for every page loaded into memory, shared across all collections.
+///
+/// The pool does not own any pages. Page sets report the size of the pages they load and free,
+/// and evict their least recently used pages once the pool is over its budget.
+pub struct BufferPool {
+    /// The maximum amount of bytes loaded pages should occupy.
+    limit: usize,
+    /// The amount of bytes currently occupied by loaded pages.
+    used: AtomicUsize,
+    /// Logical clock used to order page accesses.
+    clock: AtomicU64,
+    /// Page accesses that were served from memory.
+    hits: AtomicU64,
+    /// Page accesses that had to be loaded from the filesystem.
+    misses: AtomicU64,
+    /// Pages freed to stay under the memory budget.
+    evictions: AtomicU64,
+    /// Dirty pages written to the filesystem before being evicted.
+    write_backs: AtomicU64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A snapshot of the buffer pool counters.
+pub struct BufferPoolStats {
+    pub limit: usize,
+    pub used: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    pub write_backs: u64,
+}
+
+impl BufferPool {
+    pub fn new(limit: usize) -> Self {
+        BufferPool {
+            limit,
+            used: AtomicUsize::new(0),
+            clock: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            write_backs: AtomicU64::new(0),
+        }
+    }
+
+    /// The maximum amount of bytes loaded pages should occupy.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// The amount of bytes currently occupied by loaded pages.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    /// True if loaded pages occupy more memory than the limit.
+    pub fn is_over_budget(&self) -> bool {
+        self.used() > self.limit
+    }
+
+    /// Advances the access clock, returning the new time.
+    pub fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Accounts for a page size change from `old` to `new` bytes.
+    pub fn resize(&self, old: usize, new: usize) {
+        if new > old {
+            self.used.fetch_add(new - old, Ordering::SeqCst);
+        } else {
+            self.used.fetch_sub(old - new, Ordering::SeqCst);
+        }
+    }
+
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_eviction(&self, write_back: bool) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+        if write_back {
+            self.write_backs.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> BufferPoolStats {
+        BufferPoolStats {
+            limit: self.limit,
+            used: self.used(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            write_backs: self.write_backs.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize() {
+        let pool = BufferPool::new(100);
+
+        pool.resize(0, 80);
+        assert!(!pool.is_over_budget());
+
+        pool.resize(80, 120);
+        assert!(pool.is_over_budget());
+
+        pool.resize(120, 0);
+        assert_eq!(pool.used(), 0);
+    }
+
+    #[test]
+    fn test_tick() {
+        let pool = BufferPool::new(100);
+
+        let first = pool.tick();
+        let second = pool.tick();
+
+        assert!(second > first);
+    }
+}
@@ -1,7 +1,11 @@
 use bson::de::Error;
+use serde_json::Value;
 use std::io;
 use std::string::FromUtf8Error;
 
+use crate::lib::json::schema::SchemaViolation;
+use crate::page::record_id::RecordId;
+
 #[derive(Debug)]
 /// Error that occurs when attempting to read BSON documents from a page.
 pub enum ReadError {
@@ -13,6 +17,30 @@ pub enum ReadError {
     CorruptedHeader(FromUtf8Error),
     /// Improper key value formatting.
     MalformedHeader,
+    /// The page does not exist in the collection.
+    PageNotFound(u32),
+    /// A required header key is not present.
+    MissingHeaderKey(String),
+    /// A header value could not be parsed.
+    MalformedHeaderValue { key: String, value: String },
+    /// The header was written by a newer build.
+    UnsupportedHeaderVersion(u32),
+    /// The page body does not match its stored checksums.
+    ChecksumMismatch {
+        collection: String,
+        page: u32,
+        /// Byte offset of the damaged record in the page body, or 0 if the damage could only be
+        /// detected for the page as a whole.
+        offset: usize,
+    },
+    /// A record reassembled from overflow pages does not match its checksum.
+    OverflowChecksumMismatch {
+        collection: String,
+        page: u32,
+        slot: u32,
+    },
+    /// The header contents do not match the header checksum.
+    HeaderChecksumMismatch { expected: u32, actual: u32 },
 }
 
 impl From<bson::de::Error> for ReadError {
@@ -30,6 +58,31 @@ pub enum WriteError {
     Io(io::Error),
     /// The data attempting to be written will overflow the page size.
     PageSizeExceeded(usize),
+    /// The page must be loaded into memory for the operation.
+    PageNotLoaded(u32),
+    /// No slot exists at the record id.
+    SlotNotFound(RecordId),
+    /// A page could not be read before writing to it.
+    Read(ReadError),
+    /// The collection was dropped or renamed.
+    CollectionClosed(String),
+    /// The document does not match the schema of the collection.
+    SchemaViolation(SchemaViolation),
+    /// The write would move or remove a document of a capped collection, whose documents are
+    /// only ever appended.
+    CappedCollection(String),
+    /// Another document of the collection has the `_id`.
+    DuplicateId(Value),
+    /// The `_id` is null or an array.
+    InvalidId(Value),
+    /// An update operator does not apply to a value of the document.
+    InvalidUpdate(String),
+}
+
+impl From<ReadError> for WriteError {
+    fn from(e: ReadError) -> Self {
+        WriteError::Read(e)
+    }
 }
 
 impl From<io::Error> for WriteError {
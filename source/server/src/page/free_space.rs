@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+
+/// Tracks the free space of every page in a collection so inserts fill the holes left by deleted
+/// and moved documents before new pages are created.
+///
+/// The map is rebuilt from the page metadata when a collection is opened and updated whenever a
+/// page is written.
+pub struct FreeSpaceMap {
+    /// Free bytes of each page, by page id.
+    pages: BTreeMap<u32, usize>,
+}
+
+impl FreeSpaceMap {
+    pub fn new() -> Self {
+        FreeSpaceMap {
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Records the free space of a page.
+    pub fn update(&mut self, page: u32, free: usize) {
+        self.pages.insert(page, free);
+    }
+
+    /// Stops tracking a page.
+    pub fn remove(&mut self, page: u32) {
+        self.pages.remove(&page);
+    }
+
+    /// The free space of a page, if it is tracked.
+    pub fn free(&self, page: u32) -> Option<usize> {
+        self.pages.get(&page).cloned()
+    }
+
+    /// The lowest page id with at least `needed` free bytes, ignoring the `exclude` page.
+    pub fn find(&self, needed: usize, exclude: Option<u32>) -> Option<u32> {
+        self.pages
+            .iter()
+            .find(|(id, free)| **free >= needed && Some(**id) != exclude)
+            .map(|(id, _)| *id)
+    }
+
+    /// The total free bytes across every page.
+    pub fn total(&self) -> usize {
+        self.pages.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let mut map = FreeSpaceMap::new();
+        map.update(0, 10);
+        map.update(1, 100);
+        map.update(2, 200);
+
+        assert_eq!(map.find(50, None), Some(1));
+        assert_eq!(map.find(50, Some(1)), Some(2));
+        assert_eq!(map.find(500, None), None);
+
+        map.update(0, 60);
+        assert_eq!(map.find(50, None), Some(0));
+
+        map.remove(0);
+        assert_eq!(map.find(50, None), Some(1));
+        assert_eq!(map.total(), 300);
+    }
+}
@@ -1,20 +1,74 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::convert::TryFrom;
 use std::io::{BufRead, Cursor};
+use std::str::FromStr;
 
+use chrono::Utc;
+
+use crate::io::compressor::CompressionStrategy;
 use crate::page::error::ReadError;
 
+/// Identifies a versioned page metadata header. Headers without it were written by builds that
+/// predate header versioning and are read as version 0.
+pub const META_MAGIC: &str = "IRISPAGE";
+/// The header format version written by this build.
+///
+/// Version 2 pages have a slotted body. Pages of earlier versions store their documents as a plain
+/// sequence of records and are converted to slotted pages when they are next written.
+pub const META_VERSION: u32 = 2;
+/// The first header version with a slotted page body.
+pub const SLOTTED_VERSION: u32 = 2;
+
+const VERSION_KEY: &str = "VERSION";
+const COUNT_KEY: &str = "COUNT";
+const POS_KEY: &str = "POS";
+const CREATED_KEY: &str = "CREATED";
+const FREE_KEY: &str = "FREE";
+const BODY_CHECKSUM_KEY: &str = "BODY_CHECKSUM";
+const RECORD_CHECKSUMS_KEY: &str = "RECORD_CHECKSUMS";
+const COMPRESSION_KEY: &str = "COMPRESSION";
+
+/// `COMPRESSION` value of an uncompressed page body.
+const NO_COMPRESSION: &str = "none";
+const CHECKSUM_KEY: &str = "CHECKSUM";
+
 /// A page metadata file is a key-value string that contains metadata about the page. Header
-/// key-pairs are separated by a whitespace: `KEY1=VALUE KEY2=VALUE`.
+/// key-pairs are separated by a whitespace and prefixed by the magic number:
+/// `IRISPAGE KEY1=VALUE KEY2=VALUE`.
 ///
 /// Each header contains the following key-value pairs.
+/// * `VERSION` - The header format version
 /// * `COUNT` - The amount of BSON documents
-/// * `POS` - The next available page file
+/// * `POS` - The next available page file, superseded by the collection free space map
+/// * `CREATED` - When the page was created, in seconds since the unix epoch
+/// * `FREE` - Bytes still available in the page body, `0` if unknown
+/// * `BODY_CHECKSUM` - CRC32C of the page body, absent if the body was never written
+/// * `RECORD_CHECKSUMS` - `1` if every document record is followed by its CRC32C
+/// * `COMPRESSION` - Algorithm the page body is compressed with, or `none`
+/// * `CHECKSUM` - CRC32C of the header contents preceding it, always the last key
+///
+/// Keys that are not known by this build are kept as is and written back out.
+#[derive(Debug, Clone, PartialEq)]
 pub struct PageMetadata {
+    /// Header format version the metadata was read with.
+    pub version: u32,
     /// BSON document count.
     pub count: u64,
-    /// Next page with available disk space.
+    /// Next page with available disk space. Only kept for compatibility, free space is tracked
+    /// by the collection free space map from the `free` value of each page.
     pub pos: u64,
+    /// Creation time of the page, in seconds since the unix epoch.
+    pub created: i64,
+    /// Bytes still available in the page body.
+    pub free: u64,
+    /// CRC32C of the page body.
+    pub body_checksum: Option<u32>,
+    /// True if every document record in the page body is followed by its CRC32C.
+    pub record_checksums: bool,
+    /// Algorithm the page body is compressed with.
+    pub compression: Option<CompressionStrategy>,
+    /// Key-value pairs not known by this build.
+    pub extra: BTreeMap<String, String>,
 }
 
 impl TryFrom<Vec<u8>> for PageMetadata {
@@ -35,27 +89,61 @@ impl TryFrom<Vec<u8>> for PageMetadata {
             Err(e) => return Err(ReadError::CorruptedHeader(e)),
         };
 
-        let mut kv_pairs: HashMap<String, _> = HashMap::new();
+        if !header.starts_with(META_MAGIC) {
+            // Version 0 headers have no magic number and place each key on its own line.
+            let contents =
+                String::from_utf8(cursor.into_inner()).map_err(ReadError::CorruptedHeader)?;
+            return Self::from_legacy(&contents);
+        }
+
+        let header = header.trim_end();
+        let (body, checksum) = match header.rsplit_once(' ') {
+            Some((body, last)) if last.starts_with(&format!("{}=", CHECKSUM_KEY)) => (body, last),
+            _ => return Err(ReadError::MissingHeaderKey(CHECKSUM_KEY.to_string())),
+        };
 
-        let kv_pairs_str = header.split_whitespace();
-        for kv_str in kv_pairs_str {
-            let kv = kv_str.split_once('=');
-            let kv = match kv {
-                Some(kv) => kv,
-                None => return Err(ReadError::MalformedHeader),
-            };
+        let mut kv_pairs = parse_kv_pairs(body[META_MAGIC.len()..].split_whitespace())?;
+        let checksum = parse_kv_pairs(checksum.split_whitespace())?;
 
-            kv_pairs.insert(kv.0.to_string(), kv.1);
+        let expected: u32 = parse_hex(&checksum, CHECKSUM_KEY)?;
+        let actual = crc32c::crc32c(body.as_bytes());
+        if expected != actual {
+            return Err(ReadError::HeaderChecksumMismatch { expected, actual });
         }
 
-        Ok(PageMetadata { count: 0, pos: 0 })
+        let version = take_value(&mut kv_pairs, VERSION_KEY)?;
+        if version > META_VERSION {
+            return Err(ReadError::UnsupportedHeaderVersion(version));
+        }
+
+        Ok(PageMetadata {
+            version,
+            count: take_value(&mut kv_pairs, COUNT_KEY)?,
+            pos: take_value(&mut kv_pairs, POS_KEY)?,
+            created: take_value(&mut kv_pairs, CREATED_KEY)?,
+            free: take_value_or(&mut kv_pairs, FREE_KEY, 0)?,
+            body_checksum: take_hex(&mut kv_pairs, BODY_CHECKSUM_KEY)?,
+            record_checksums: take_flag(&mut kv_pairs, RECORD_CHECKSUMS_KEY)?,
+            compression: take_compression(&mut kv_pairs)?,
+            extra: kv_pairs,
+        })
     }
 }
 
 impl PageMetadata {
     /// Initializes a new PageMetadata object, not saving it to the disk.
     pub fn new() -> Self {
-        PageMetadata { count: 0, pos: 0 }
+        PageMetadata {
+            version: META_VERSION,
+            count: 0,
+            pos: 0,
+            created: Utc::now().timestamp(),
+            free: 0,
+            body_checksum: None,
+            record_checksums: false,
+            compression: None,
+            extra: BTreeMap::new(),
+        }
     }
 
     /// The file name where the metadata should be written to.
@@ -63,15 +151,196 @@ impl PageMetadata {
         format!("{}.{}", collection_name, self.pos)
     }
 
+    /// Serializes the metadata, labelled with the version of the page body it describes. Pages
+    /// are staged with the current version before they are written.
     pub fn as_bytes(&self) -> Vec<u8> {
-        format!("COUNT={}\nPOS={}", self.count, self.pos).into_bytes()
+        let mut body = format!(
+            "{} {}={} {}={} {}={} {}={} {}={}",
+            META_MAGIC,
+            VERSION_KEY,
+            self.version,
+            COUNT_KEY,
+            self.count,
+            POS_KEY,
+            self.pos,
+            CREATED_KEY,
+            self.created,
+            FREE_KEY,
+            self.free
+        );
+
+        if let Some(checksum) = self.body_checksum {
+            body.push_str(&*format!(" {}={:08x}", BODY_CHECKSUM_KEY, checksum));
+        }
+
+        body.push_str(&*format!(
+            " {}={}",
+            RECORD_CHECKSUMS_KEY, self.record_checksums as u8
+        ));
+
+        body.push_str(&*format!(
+            " {}={}",
+            COMPRESSION_KEY,
+            self.compression
+                .map(|c| c.as_str())
+                .unwrap_or(NO_COMPRESSION)
+        ));
+
+        for (k, v) in &self.extra {
+            body.push_str(&*format!(" {}={}", k, v));
+        }
+
+        let checksum = crc32c::crc32c(body.as_bytes());
+
+        format!("{} {}={:08x}\n", body, CHECKSUM_KEY, checksum).into_bytes()
+    }
+
+    /// Reads a version 0 header, which only contains `COUNT` and `POS`.
+    fn from_legacy(header: &str) -> Result<Self, ReadError> {
+        let mut kv_pairs = parse_kv_pairs(header.split_whitespace())?;
+
+        Ok(PageMetadata {
+            version: 0,
+            count: take_value(&mut kv_pairs, COUNT_KEY)?,
+            pos: take_value(&mut kv_pairs, POS_KEY)?,
+            created: 0,
+            free: 0,
+            body_checksum: None,
+            record_checksums: false,
+            compression: None,
+            extra: kv_pairs,
+        })
+    }
+}
+
+/// Splits `KEY=VALUE` strings into a map.
+fn parse_kv_pairs<'a, I>(kv_pairs_str: I) -> Result<BTreeMap<String, String>, ReadError>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let mut kv_pairs = BTreeMap::new();
+
+    for kv_str in kv_pairs_str {
+        let kv = kv_str.split_once('=');
+        let kv = match kv {
+            Some(kv) => kv,
+            None => return Err(ReadError::MalformedHeader),
+        };
+
+        kv_pairs.insert(kv.0.to_string(), kv.1.to_string());
+    }
+
+    Ok(kv_pairs)
+}
+
+/// Removes a key from the header, parsing its value.
+fn take_value<T>(kv_pairs: &mut BTreeMap<String, String>, key: &str) -> Result<T, ReadError>
+where
+    T: FromStr,
+{
+    let value = match kv_pairs.remove(key) {
+        Some(v) => v,
+        None => return Err(ReadError::MissingHeaderKey(key.to_string())),
+    };
+
+    value.parse().map_err(|_| ReadError::MalformedHeaderValue {
+        key: key.to_string(),
+        value,
+    })
+}
+
+/// Removes an optional key from the header, parsing its value.
+fn take_value_or<T>(
+    kv_pairs: &mut BTreeMap<String, String>,
+    key: &str,
+    default: T,
+) -> Result<T, ReadError>
+where
+    T: FromStr,
+{
+    if !kv_pairs.contains_key(key) {
+        return Ok(default);
+    }
+
+    take_value(kv_pairs, key)
+}
+
+/// Removes an optional hexadecimal value from the header.
+fn take_hex(kv_pairs: &mut BTreeMap<String, String>, key: &str) -> Result<Option<u32>, ReadError> {
+    if !kv_pairs.contains_key(key) {
+        return Ok(None);
+    }
+
+    let value = parse_hex(kv_pairs, key)?;
+    kv_pairs.remove(key);
+
+    Ok(Some(value))
+}
+
+/// Removes an optional `0` or `1` flag from the header.
+fn take_flag(kv_pairs: &mut BTreeMap<String, String>, key: &str) -> Result<bool, ReadError> {
+    if !kv_pairs.contains_key(key) {
+        return Ok(false);
+    }
+
+    match take_value::<u8>(kv_pairs, key)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        value => Err(ReadError::MalformedHeaderValue {
+            key: key.to_string(),
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// Removes the optional compression algorithm from the header.
+fn take_compression(
+    kv_pairs: &mut BTreeMap<String, String>,
+) -> Result<Option<CompressionStrategy>, ReadError> {
+    let value = match kv_pairs.remove(COMPRESSION_KEY) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    if value == NO_COMPRESSION {
+        return Ok(None);
     }
+
+    match value.parse() {
+        Ok(c) => Ok(Some(c)),
+        Err(_) => Err(ReadError::MalformedHeaderValue {
+            key: COMPRESSION_KEY.to_string(),
+            value,
+        }),
+    }
+}
+
+/// Parses a hexadecimal header value.
+fn parse_hex(kv_pairs: &BTreeMap<String, String>, key: &str) -> Result<u32, ReadError> {
+    let value = match kv_pairs.get(key) {
+        Some(v) => v,
+        None => return Err(ReadError::MissingHeaderKey(key.to_string())),
+    };
+
+    u32::from_str_radix(value, 16).map_err(|_| ReadError::MalformedHeaderValue {
+        key: key.to_string(),
+        value: value.clone(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn metadata() -> PageMetadata {
+        let mut metadata = PageMetadata::new();
+        metadata.count = 32;
+        metadata.pos = 64;
+        metadata.created = 1620000000;
+        metadata.free = 128;
+        metadata
+    }
+
     #[test]
     fn test_file_name() {
         let mut metadata = PageMetadata::new();
@@ -82,12 +351,105 @@ mod tests {
 
     #[test]
     fn test_as_bytes() {
-        let mut metadata = PageMetadata::new();
-        metadata.count = 32;
-        metadata.pos = 64;
+        let metadata = metadata();
+
+        let body = "IRISPAGE VERSION=2 COUNT=32 POS=64 CREATED=1620000000 FREE=128 \
+                    RECORD_CHECKSUMS=0 COMPRESSION=none";
+        let expected = format!(
+            "{} CHECKSUM={:08x}\n",
+            body,
+            crc32c::crc32c(body.as_bytes())
+        );
+
+        assert_eq!(expected.as_bytes(), &*metadata.as_bytes());
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<(), ReadError> {
+        let mut metadata = metadata();
+        metadata.body_checksum = Some(0xdeadbeef);
+        metadata.record_checksums = true;
+        metadata.compression = Some(CompressionStrategy::Snappy);
+        metadata.extra.insert("OWNER".into(), "iris".into());
+
+        let read = PageMetadata::try_from(metadata.as_bytes())?;
+
+        assert_eq!(read, metadata);
+
+        // The version of an older page body is kept.
+        metadata.version = 1;
+        assert_eq!(PageMetadata::try_from(metadata.as_bytes())?.version, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_version_1() -> Result<(), ReadError> {
+        let body = "IRISPAGE VERSION=1 COUNT=32 POS=64 CREATED=1620000000 RECORD_CHECKSUMS=0";
+        let header = format!(
+            "{} CHECKSUM={:08x}\n",
+            body,
+            crc32c::crc32c(body.as_bytes())
+        );
+
+        let read = PageMetadata::try_from(header.into_bytes())?;
+
+        assert_eq!(read.version, 1);
+        assert_eq!(read.free, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_legacy() -> Result<(), ReadError> {
+        let read = PageMetadata::try_from(b"COUNT=32\nPOS=64".to_vec())?;
+
+        assert_eq!(read.version, 0);
+        assert_eq!(read.count, 32);
+        assert_eq!(read.pos, 64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_key() {
+        let err = PageMetadata::try_from(b"COUNT=32".to_vec())
+            .err()
+            .expect("missing key");
+
+        assert!(matches!(err, ReadError::MissingHeaderKey(key) if key == POS_KEY));
+    }
+
+    #[test]
+    fn test_malformed_value() {
+        let body = "IRISPAGE VERSION=1 COUNT=abc POS=64 CREATED=1620000000";
+        let header = format!(
+            "{} CHECKSUM={:08x}\n",
+            body,
+            crc32c::crc32c(body.as_bytes())
+        );
+
+        let err = PageMetadata::try_from(header.into_bytes())
+            .err()
+            .expect("malformed value");
+
+        assert!(matches!(
+            err,
+            ReadError::MalformedHeaderValue { key, value } if key == COUNT_KEY && value == "abc"
+        ));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let bytes = String::from_utf8(metadata().as_bytes())
+            .unwrap()
+            .replace("COUNT=32", "COUNT=33")
+            .into_bytes();
 
-        let expected = b"COUNT=32\nPOS=64";
+        let err = PageMetadata::try_from(bytes)
+            .err()
+            .expect("checksum mismatch");
 
-        assert_eq!(expected, &*metadata.as_bytes());
+        assert!(matches!(err, ReadError::HeaderChecksumMismatch { .. }));
     }
 }
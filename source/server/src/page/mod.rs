@@ -1,4 +1,9 @@
+pub mod buffer_pool;
 pub mod error;
+pub mod free_space;
 pub mod metadata;
 pub mod page;
 pub mod page_set;
+pub mod record_id;
+pub mod slot;
+pub mod wal;
@@ -1,18 +1,35 @@
-use std::fs;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 
+use crate::io::file_descriptor::FileDescriptor;
+use crate::io::filesystem::{Filesystem, StdFs};
 use crate::io::path::DatabasePath;
+use crate::lib::json::bsonio::decoder;
 use crate::lib::json::types::JsonObject;
+use crate::page::buffer_pool::BufferPool;
 use crate::page::error::{ReadError, WriteError};
-use crate::page::metadata::PageMetadata;
+use crate::page::metadata::{PageMetadata, META_VERSION, SLOTTED_VERSION};
+use crate::page::record_id::RecordId;
+use crate::page::slot::{
+    decode_overflow, decode_slots, encode_slots, EncodedSlots, OverflowStub, RawSlot, Slot,
+};
+use crate::page::wal::{Durability, FileImage, WriteAheadLog};
 use crate::storage::document::Document;
+use crate::storage::options::CollectionOptions;
 use crate::storage::utils::CollectionNameFormatter;
 
-/// The maximum amount of data that is able to fit on a single page.
+/// The default maximum amount of data that is able to fit on a single page, used unless a
+/// collection sets its own page size.
 ///
 /// The standard maximum is 2MB.
-pub const MAX_PAGE_SIZE: usize = 2E6 as usize;
+pub const DEFAULT_PAGE_SIZE: usize = 2E6 as usize;
 /// File extension of the page metadata.
 pub const META_PAGE_EXT: &str = "meta";
+/// File extension of the overflow pages of a page.
+pub const OVERFLOW_PAGE_EXT: &str = "overflow";
 
 pub trait PageReadable {
     fn read(o: JsonObject) -> Self;
@@ -24,57 +41,925 @@ pub trait PageWriteable {
 
 /// A single chunk of data from a database collection. Can be loaded in and out from memory when
 /// necessary.
+///
+/// A page is stored as two files in the data directory:
+/// * `<collection>.<id>` - The page body, a slot directory followed by the slot records
+/// * `<collection>.<id>.meta` - The page metadata
+///
+/// Each record is followed by its CRC32C if the collection stores record checksums. The body is
+/// compressed as a whole if the collection sets a compression algorithm. See
+/// [`encode_slots`](crate::page::slot::encode_slots) for the slotted body layout.
+///
+/// Records larger than the page size are split across a chain of overflow pages, stored as
+/// `<collection>.<id>.overflow.<slot>.<n>` for the `n`th chunk of the record. Overflow pages are
+/// read together with the page body, so the page contents always hold whole documents.
 pub struct Page {
     collection_name: CollectionNameFormatter,
     /// The page id for the collection.
+    id: u32,
+    /// Metadata describing the page contents.
     metadata: PageMetadata,
-    /// Document data possibly loaded in memory. If the documents is None, then the page is not
-    /// loaded into memory.
-    documents: Option<Box<Vec<Document>>>,
+    /// Storage options of the collection the page belongs to.
+    options: Arc<CollectionOptions>,
+    /// Slots possibly loaded in memory. If the slots are None, then the page is not loaded into
+    /// memory.
+    slots: Option<Box<Vec<Slot>>>,
+    /// Records of the loaded slots that are stored in overflow pages, by slot index.
+    overflow: BTreeMap<u32, OverflowStub>,
+    /// The buffer pool the loaded documents are accounted to.
+    pool: Arc<BufferPool>,
+    /// The log every write to the page files goes through.
+    wal: Arc<WriteAheadLog>,
+    /// Size of the loaded documents in bytes.
+    size: usize,
+    /// Amount of users currently holding the page. A pinned page is never freed.
+    pins: u32,
+    /// True if the documents in memory have not been written to the filesystem.
+    dirty: bool,
+    /// Buffer pool time of the last access. Updated by readers holding a shared lock.
+    last_used: AtomicU64,
 }
 
 impl Page {
     /// Creates a new page on the filesystem if it does not exist.
-    pub fn create(collection_name: String) -> Self {
-        todo!()
+    pub fn create(
+        collection_name: CollectionNameFormatter,
+        id: u32,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: Arc<CollectionOptions>,
+    ) -> Result<Self, WriteError> {
+        let mut page = Page::new(collection_name, id, pool, wal, options);
+
+        if !StdFs.file_exists(&page.body_file()) {
+            page.metadata.free = page.options.page_size as u64;
+            page.fwrite(Vec::new())
+                .map_err(|e| WriteError::CouldNotCreatePage(e))?;
+        }
+
+        Ok(page)
+    }
+
+    /// Opens an existing page, loading its metadata but not its contents.
+    pub fn open(
+        collection_name: CollectionNameFormatter,
+        id: u32,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: Arc<CollectionOptions>,
+    ) -> Result<Self, ReadError> {
+        let mut page = Page::new(collection_name, id, pool, wal, options);
+
+        page.fread_meta()?;
+
+        Ok(page)
+    }
+
+    fn new(
+        collection_name: CollectionNameFormatter,
+        id: u32,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: Arc<CollectionOptions>,
+    ) -> Self {
+        Page {
+            collection_name,
+            id,
+            metadata: PageMetadata::new(),
+            options,
+            slots: None,
+            overflow: BTreeMap::new(),
+            pool,
+            wal,
+            size: 0,
+            pins: 0,
+            dirty: false,
+            last_used: AtomicU64::new(0),
+        }
+    }
+
+    /// The page id for the collection.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// True if the page contents are loaded into memory.
+    pub fn is_loaded(&self) -> bool {
+        self.slots.is_some()
+    }
+
+    /// True if the page is in use and cannot be freed.
+    pub fn is_pinned(&self) -> bool {
+        self.pins > 0
+    }
+
+    /// True if the page contents in memory have not been written to the filesystem.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Size of the loaded page contents in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Buffer pool time of the last access.
+    pub fn last_used(&self) -> u64 {
+        self.last_used.load(Ordering::SeqCst)
+    }
+
+    /// Records an access to the page.
+    pub fn touch(&self) {
+        self.last_used.store(self.pool.tick(), Ordering::SeqCst);
+    }
+
+    /// Marks the page as in use, preventing it from being freed.
+    pub fn pin(&mut self) {
+        self.pins += 1;
+        self.touch();
+    }
+
+    /// Releases a previous pin.
+    pub fn unpin(&mut self) {
+        self.pins = self.pins.saturating_sub(1);
+    }
+
+    /// Metadata describing the page contents.
+    pub fn metadata(&self) -> &PageMetadata {
+        &self.metadata
+    }
+
+    /// Bytes still available in the page body.
+    pub fn free_space(&self) -> usize {
+        self.metadata.free as usize
     }
 
     /// Loads the page contents into memory.
     pub fn read(&mut self) -> Result<(), ReadError> {
-        todo!()
+        self.fread_meta()?;
+
+        let stored = StdFs
+            .read(&self.body_file())
+            .map_err(|e| ReadError::Io(e))?;
+        let bytes = self.unpack_body(stored, &self.metadata)?;
+        let (slots, overflow) = self.decode_body(&bytes, &self.metadata)?;
+        let size = bytes.len() + overflow_len(&overflow);
+
+        self.metadata.count = count_documents(&slots);
+        self.slots = Some(Box::new(slots));
+        self.overflow = overflow;
+        self.dirty = false;
+        self.resize(size);
+
+        Ok(())
     }
 
-    /// Frees the page contents from memory.
-    pub fn free(&mut self) {
-        let contents = &mut self.documents;
-        if contents.is_some() {
-            let contents = contents.as_mut().unwrap();
-            contents.clear();
-        }
+    /// Verifies the page body on the filesystem against its checksums, without loading it into
+    /// memory.
+    pub fn scrub(&self) -> Result<(), ReadError> {
+        let meta_bytes = StdFs
+            .read(&self.meta_file())
+            .map_err(|e| ReadError::Io(e))?;
+        let metadata = PageMetadata::try_from(meta_bytes)?;
+
+        let stored = StdFs
+            .read(&self.body_file())
+            .map_err(|e| ReadError::Io(e))?;
+        let bytes = self.unpack_body(stored, &metadata)?;
+        self.decode_body(&bytes, &metadata)?;
+
+        Ok(())
+    }
+
+    /// Frees the page contents from memory, writing them to the filesystem first if they were
+    /// modified.
+    pub fn free(&mut self) -> Result<(), WriteError> {
+        self.flush()?;
+
+        self.slots = None;
+        self.overflow.clear();
+        self.resize(0);
+
+        Ok(())
     }
 
     /// Get the page contents from memory if loaded in memory.
-    pub fn data(&self) -> &Option<Box<Vec<Document>>> {
-        &self.documents
+    pub fn slots(&self) -> &Option<Box<Vec<Slot>>> {
+        &self.slots
+    }
+
+    /// Get a single slot of the page if loaded in memory.
+    pub fn slot(&self, index: u32) -> Option<&Slot> {
+        self.slots.as_ref()?.get(index as usize)
+    }
+
+    /// Get the document stored in a slot of the page if loaded in memory, without following a
+    /// forwarding pointer.
+    pub fn document(&self, index: u32) -> Option<&Document> {
+        self.slot(index)?.document()
+    }
+
+    /// Places a slot in the first tombstone of the page or after the last slot, returning its
+    /// index. Returns None if the page does not have enough space for the slot.
+    pub fn insert(
+        &mut self,
+        slot: Slot,
+        durability: Durability,
+    ) -> Result<Option<u32>, WriteError> {
+        let mut slots = self.loaded_slots()?.clone();
+
+        let index = match slots.iter().position(|s| *s == Slot::Tombstone) {
+            Some(index) => {
+                slots[index] = slot;
+                index
+            }
+            None => {
+                slots.push(slot);
+                slots.len() - 1
+            }
+        };
+
+        match self.write(slots, durability) {
+            Ok(()) => Ok(Some(index as u32)),
+            Err(WriteError::PageSizeExceeded(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Replaces an existing slot of the page. Returns false if the page does not have enough
+    /// space for the new slot, leaving the page unchanged.
+    pub fn set(
+        &mut self,
+        index: u32,
+        slot: Slot,
+        durability: Durability,
+    ) -> Result<bool, WriteError> {
+        let mut slots = self.loaded_slots()?.clone();
+
+        match slots.get_mut(index as usize) {
+            Some(s) => *s = slot,
+            None => return Err(WriteError::SlotNotFound(RecordId::new(self.id, index))),
+        }
+
+        match self.write(slots, durability) {
+            Ok(()) => Ok(true),
+            Err(WriteError::PageSizeExceeded(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
     /// Updates the page contents. If the page is loaded into memory, the contents are updated in
     /// memory as well as on the filesystem, otherwise only updating on the filesystem.
-    pub fn write(&mut self, new: Vec<Document>) {
-        todo!()
+    pub fn write(&mut self, new: Vec<Slot>, durability: Durability) -> Result<(), WriteError> {
+        let mut staged = self.stage(new)?;
+
+        self.wal.write(staged.take_images(), durability)?;
+        self.apply(staged);
+
+        Ok(())
+    }
+
+    /// Encodes new page contents without writing them. The contents take effect once the file
+    /// images of the staged write are written through the write-ahead log and the staged write is
+    /// applied to the page.
+    ///
+    /// Overflow pages are only written for records that changed since the page was loaded, and
+    /// overflow pages no longer in use are removed.
+    pub fn stage(&self, new: Vec<Slot>) -> Result<StagedWrite, WriteError> {
+        let EncodedSlots { body, overflow } = self.encode_body(&new)?;
+        let body_len = body.len();
+        let stored = self.pack_body(body);
+
+        let mut metadata = self.metadata.clone();
+        metadata.version = META_VERSION;
+        metadata.count = count_documents(&new);
+        metadata.free = (self.options.page_size - body_len) as u64;
+        metadata.body_checksum = Some(crc32c::crc32c(&stored));
+        metadata.record_checksums = self.options.record_checksums;
+        metadata.compression = self.options.compression;
+
+        let mut images = vec![
+            FileImage::new(self.body_file().name, stored),
+            FileImage::new(self.meta_file().name, metadata.as_bytes()),
+        ];
+
+        let mut stubs = BTreeMap::new();
+        for (index, stub, record) in overflow {
+            if self.overflow.get(&index) != Some(&stub) {
+                for (chunk, bytes) in record.chunks(self.options.page_size).enumerate() {
+                    let name = self.overflow_file(index, chunk as u32).name;
+                    images.push(FileImage::new(name, bytes.to_vec()));
+                }
+            }
+
+            stubs.insert(index, stub);
+        }
+
+        for (index, stub) in self.overflow.iter() {
+            let chunks = stubs.get(index).map(|s| s.chunks).unwrap_or(0);
+            for chunk in chunks..stub.chunks {
+                images.push(FileImage::removed(self.overflow_file(*index, chunk).name));
+            }
+        }
+
+        let size = body_len + overflow_len(&stubs);
+
+        Ok(StagedWrite {
+            images,
+            metadata,
+            slots: new,
+            overflow: stubs,
+            size,
+        })
+    }
+
+    /// Applies a staged write after its file images were written.
+    pub fn apply(&mut self, staged: StagedWrite) {
+        self.metadata = staged.metadata;
+
+        if self.slots.is_some() {
+            self.slots = Some(Box::new(staged.slots));
+            self.overflow = staged.overflow;
+            self.dirty = false;
+            self.resize(staged.size);
+        }
+    }
+
+    /// File images that remove the page from the filesystem, along with its overflow pages. The
+    /// overflow pages of a page that is not loaded are found by listing the collection files.
+    pub fn removal(&self) -> io::Result<Vec<FileImage>> {
+        let mut images = vec![
+            FileImage::removed(self.body_file().name),
+            FileImage::removed(self.meta_file().name),
+        ];
+
+        if self.is_loaded() {
+            for (index, stub) in self.overflow.iter() {
+                for chunk in 0..stub.chunks {
+                    images.push(FileImage::removed(self.overflow_file(*index, chunk).name));
+                }
+            }
+
+            return Ok(images);
+        }
+
+        let directory = FileDescriptor {
+            path: DatabasePath::Data,
+            name: self.collection_name.directory().to_string(),
+        };
+        let prefix = format!("{}.{}.", self.body_file().name, OVERFLOW_PAGE_EXT);
+
+        for file in StdFs.list_files(&directory)? {
+            let name = match &*directory.name {
+                "" => file,
+                dir => format!("{}/{}", dir, file),
+            };
+            if name.starts_with(&prefix) {
+                images.push(FileImage::removed(name));
+            }
+        }
+
+        Ok(images)
+    }
+
+    /// True if the slots fit on a single page.
+    pub fn fits(&self, slots: &Vec<Slot>) -> bool {
+        self.encode_body(slots).is_ok()
+    }
+
+    /// True if the page was written with different storage options or an earlier format than
+    /// the ones it would be written with now.
+    pub fn is_outdated(&self) -> bool {
+        self.metadata.version < META_VERSION
+            || self.metadata.record_checksums != self.options.record_checksums
+            || self.metadata.compression != self.options.compression
+    }
+
+    /// Updates the page contents in memory only, marking the page as dirty. The contents are
+    /// written to the filesystem when the page is flushed or freed.
+    pub fn update(&mut self, new: Vec<Slot>) -> Result<(), WriteError> {
+        if !self.is_loaded() {
+            return Err(WriteError::PageNotLoaded(self.id));
+        }
+
+        let encoded = self.encode_body(&new)?;
+        let body_len = encoded.body.len();
+        let size = body_len
+            + encoded
+                .overflow
+                .iter()
+                .map(|o| o.1.len as usize)
+                .sum::<usize>();
+
+        self.metadata.count = count_documents(&new);
+        self.metadata.free = (self.options.page_size - body_len) as u64;
+        self.slots = Some(Box::new(new));
+        self.dirty = true;
+        self.resize(size);
+
+        Ok(())
+    }
+
+    /// Writes modified page contents to the filesystem.
+    pub fn flush(&mut self) -> Result<(), WriteError> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let slots = self.slots.as_ref().unwrap().to_vec();
+
+        self.write(slots, self.options.durability)
+    }
+
+    /// Read the page metadata from the filesystem.
+    pub fn fread_meta(&mut self) -> Result<(), ReadError> {
+        let bytes = StdFs
+            .read(&self.meta_file())
+            .map_err(|e| ReadError::Io(e))?;
+
+        self.metadata = PageMetadata::try_from(bytes)?;
+
+        Ok(())
+    }
+
+    /// Write the stored page body and the page metadata to the filesystem through the
+    /// write-ahead log.
+    fn fwrite(&self, stored: Vec<u8>) -> io::Result<()> {
+        self.wal.write(
+            vec![
+                FileImage::new(self.body_file().name, stored),
+                FileImage::new(self.meta_file().name, self.metadata.as_bytes()),
+            ],
+            self.options.durability,
+        )
+    }
+
+    /// The loaded slots, failing if the page is not loaded into memory.
+    fn loaded_slots(&self) -> Result<&Vec<Slot>, WriteError> {
+        match &self.slots {
+            Some(slots) => Ok(slots),
+            None => Err(WriteError::PageNotLoaded(self.id)),
+        }
+    }
+
+    /// Encodes slots into a page body, failing if the body does not fit on a single page.
+    fn encode_body(&self, slots: &Vec<Slot>) -> Result<EncodedSlots, WriteError> {
+        let page_size = self.options.page_size;
+        let encoded = encode_slots(slots, self.options.record_checksums, page_size);
+
+        if encoded.body.len() > page_size {
+            return Err(WriteError::PageSizeExceeded(encoded.body.len()));
+        }
+
+        Ok(encoded)
+    }
+
+    /// Converts an encoded page body into the form stored on the filesystem.
+    fn pack_body(&self, bytes: Vec<u8>) -> Vec<u8> {
+        match self.options.compression {
+            Some(compression) => compression.compress(&bytes),
+            None => bytes,
+        }
+    }
+
+    /// Verifies a page body read from the filesystem against its checksum, and decompresses it.
+    fn unpack_body(&self, stored: Vec<u8>, metadata: &PageMetadata) -> Result<Vec<u8>, ReadError> {
+        let intact = match metadata.body_checksum {
+            Some(checksum) => checksum == crc32c::crc32c(&stored),
+            None => true,
+        };
+
+        if !intact {
+            // The damaged record can only be located if the body is not compressed.
+            let offset = match metadata.compression {
+                None => damaged_offset(&stored, metadata),
+                Some(_) => 0,
+            };
+
+            return Err(self.checksum_mismatch(offset));
+        }
+
+        match metadata.compression {
+            Some(compression) => compression
+                .decompress(&stored)
+                .map_err(|e| ReadError::Io(e)),
+            None => Ok(stored),
+        }
     }
 
-    /// Write the page metadata to the filesystem.
-    pub fn fwrite_meta(&self) -> Result<(), WriteError> {
-        let file_name = self
-            .metadata
-            .file_name(self.collection_name.clone().into_original());
-        let bytes = self.metadata.as_bytes();
+    /// Decodes an uncompressed page body into its slots, verifying each record against its
+    /// checksum. Records stored in overflow pages are read and put back together. Bodies written
+    /// before slotted pages are read as a slot per document.
+    ///
+    /// Returns the slots and the records that are stored in overflow pages.
+    fn decode_body(
+        &self,
+        bytes: &[u8],
+        metadata: &PageMetadata,
+    ) -> Result<(Vec<Slot>, BTreeMap<u32, OverflowStub>), ReadError> {
+        let mut slots = Vec::new();
+        let mut overflow = BTreeMap::new();
+
+        if metadata.version < SLOTTED_VERSION {
+            let records = match split_records(bytes, metadata.record_checksums) {
+                Ok(records) => records,
+                Err(offset) => return Err(self.checksum_mismatch(offset)),
+            };
 
-        let err = fs::write(DatabasePath::Data.file(&file_name), bytes).err();
-        return match err {
-            None => Ok(()),
-            Some(e) => Err(e.into()),
+            for record in records {
+                slots.push(Slot::Document(decode_document(record)?));
+            }
+
+            return Ok((slots, overflow));
+        }
+
+        let raw_slots = match decode_slots(bytes, metadata.record_checksums) {
+            Ok(raw_slots) => raw_slots,
+            Err(offset) => return Err(self.checksum_mismatch(offset)),
         };
+
+        for (index, raw_slot) in raw_slots.into_iter().enumerate() {
+            let stub = match raw_slot {
+                RawSlot::Overflow(stub) => stub,
+                raw_slot => {
+                    slots.push(to_slot(raw_slot)?);
+                    continue;
+                }
+            };
+
+            let record = self.read_overflow(index as u32, &stub)?;
+            match decode_overflow(&stub, &record) {
+                Some(raw_slot) => slots.push(to_slot(raw_slot)?),
+                None => {
+                    return Err(ReadError::OverflowChecksumMismatch {
+                        collection: self.collection_name.original().clone(),
+                        page: self.id,
+                        slot: index as u32,
+                    })
+                }
+            }
+
+            overflow.insert(index as u32, stub);
+        }
+
+        Ok((slots, overflow))
+    }
+
+    /// Reads the chain of overflow pages of a record.
+    fn read_overflow(&self, index: u32, stub: &OverflowStub) -> Result<Vec<u8>, ReadError> {
+        let mut record = Vec::with_capacity(stub.len as usize);
+
+        for chunk in 0..stub.chunks {
+            let bytes = StdFs
+                .read(&self.overflow_file(index, chunk))
+                .map_err(|e| ReadError::Io(e))?;
+            record.extend(bytes);
+        }
+
+        Ok(record)
+    }
+
+    fn checksum_mismatch(&self, offset: usize) -> ReadError {
+        ReadError::ChecksumMismatch {
+            collection: self.collection_name.original().clone(),
+            page: self.id,
+            offset,
+        }
+    }
+
+    /// Accounts for a change in the size of the loaded contents.
+    fn resize(&mut self, size: usize) {
+        self.pool.resize(self.size, size);
+        self.size = size;
+    }
+
+    /// File descriptor of the page body.
+    fn body_file(&self) -> FileDescriptor {
+        FileDescriptor {
+            path: DatabasePath::Data,
+            name: self.collection_name.as_page_file_name(self.id),
+        }
+    }
+
+    /// File descriptor of a chunk of a record stored in overflow pages.
+    fn overflow_file(&self, index: u32, chunk: u32) -> FileDescriptor {
+        FileDescriptor {
+            path: DatabasePath::Data,
+            name: self
+                .collection_name
+                .as_overflow_file_name(self.id, index, chunk),
+        }
+    }
+
+    /// File descriptor of the page metadata.
+    fn meta_file(&self) -> FileDescriptor {
+        FileDescriptor {
+            path: DatabasePath::Data,
+            name: self.collection_name.as_meta_file_name(self.id),
+        }
+    }
+}
+
+/// New page contents that were encoded but not yet written.
+pub struct StagedWrite {
+    images: Vec<FileImage>,
+    metadata: PageMetadata,
+    slots: Vec<Slot>,
+    overflow: BTreeMap<u32, OverflowStub>,
+    /// Size of the encoded page body and overflow records in bytes.
+    size: usize,
+}
+
+impl StagedWrite {
+    /// Takes the file images that must be written through the write-ahead log.
+    pub fn take_images(&mut self) -> Vec<FileImage> {
+        std::mem::take(&mut self.images)
+    }
+
+    /// Size of the encoded page body and overflow records in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl Drop for Page {
+    fn drop(&mut self) {
+        self.resize(0);
+    }
+}
+
+fn decode_document(record: &[u8]) -> Result<Document, ReadError> {
+    Ok(Document::read(decoder::decode_json_object(
+        record.to_vec(),
+    )?))
+}
+
+fn to_slot(raw_slot: RawSlot) -> Result<Slot, ReadError> {
+    Ok(match raw_slot {
+        RawSlot::Tombstone => Slot::Tombstone,
+        RawSlot::Document(record) => Slot::Document(decode_document(record)?),
+        RawSlot::Forward(target) => Slot::Forward(target),
+        RawSlot::Relocated { origin, record } => Slot::Relocated {
+            origin,
+            document: decode_document(record)?,
+        },
+        RawSlot::Overflow(_) => unreachable!("overflow records are read before decoding"),
+    })
+}
+
+/// Total size of the records stored in overflow pages.
+fn overflow_len(overflow: &BTreeMap<u32, OverflowStub>) -> usize {
+    overflow.values().map(|stub| stub.len as usize).sum()
+}
+
+/// The amount of documents stored in the slots.
+fn count_documents(slots: &[Slot]) -> u64 {
+    slots.iter().filter(|s| s.document().is_some()).count() as u64
+}
+
+/// Locates the first damaged record of an uncompressed page body, or 0 if it cannot be located.
+fn damaged_offset(bytes: &[u8], metadata: &PageMetadata) -> usize {
+    if metadata.version < SLOTTED_VERSION {
+        split_records(bytes, metadata.record_checksums)
+            .err()
+            .unwrap_or(0)
+    } else {
+        decode_slots(bytes, metadata.record_checksums)
+            .err()
+            .unwrap_or(0)
+    }
+}
+
+/// Splits a page body written before slotted pages into its BSON document records. If the
+/// records are followed by checksums, each record is verified.
+///
+/// Returns the byte offset of the first damaged record on failure.
+fn split_records(bytes: &[u8], checksums: bool) -> Result<Vec<&[u8]>, usize> {
+    let checksum_len = if checksums { 4 } else { 0 };
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let header = match bytes.get(offset..offset + 4) {
+            Some(header) => header,
+            None => return Err(offset),
+        };
+
+        let len = i32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        if len < 5 || offset + len as usize + checksum_len > bytes.len() {
+            return Err(offset);
+        }
+
+        let end = offset + len as usize;
+        let record = &bytes[offset..end];
+
+        if checksums {
+            let stored = &bytes[end..end + 4];
+            let stored = u32::from_le_bytes([stored[0], stored[1], stored[2], stored[3]]);
+            if stored != crc32c::crc32c(record) {
+                return Err(offset);
+            }
+        }
+
+        records.push(record);
+        offset = end + checksum_len;
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::io::compressor::CompressionStrategy;
+    use crate::page::slot::SLOT_ENTRY_LEN;
+    use crate::page::wal::DEFAULT_BATCH_INTERVAL;
+
+    fn document(v: serde_json::Value) -> Document {
+        Document::new(v.as_object().unwrap().clone())
+    }
+
+    fn documents() -> Vec<Slot> {
+        vec![
+            Slot::Document(document(json!({ "name": "John", "age": 32 }))),
+            Slot::Document(document(json!({ "name": "Jane", "age": 24 }))),
+        ]
+    }
+
+    fn page(record_checksums: bool) -> Page {
+        page_with_options(CollectionOptions {
+            record_checksums,
+            ..Default::default()
+        })
+    }
+
+    fn page_with_options(options: CollectionOptions) -> Page {
+        Page::new(
+            CollectionNameFormatter::new("users"),
+            0,
+            Arc::new(BufferPool::new(0)),
+            Arc::new(WriteAheadLog::new(0, DEFAULT_BATCH_INTERVAL)),
+            Arc::new(options),
+        )
+    }
+
+    /// Encodes a page body, updating the page metadata the same way a write does.
+    fn encode(page: &mut Page, slots: &Vec<Slot>) -> Vec<u8> {
+        let bytes = page.pack_body(page.encode_body(slots).ok().unwrap().body);
+        page.metadata.body_checksum = Some(crc32c::crc32c(&bytes));
+        page.metadata.record_checksums = page.options.record_checksums;
+        page.metadata.compression = page.options.compression;
+        bytes
+    }
+
+    /// Decodes a page body the same way a read does.
+    fn decode(page: &Page, stored: Vec<u8>) -> Result<Vec<Slot>, ReadError> {
+        let bytes = page.unpack_body(stored, &page.metadata)?;
+        Ok(page.decode_body(&bytes, &page.metadata)?.0)
+    }
+
+    #[test]
+    fn test_encode_decode_body() -> Result<(), ReadError> {
+        for record_checksums in vec![false, true] {
+            let mut page = page(record_checksums);
+
+            let bytes = encode(&mut page, &documents());
+
+            assert_eq!(decode(&page, bytes)?, documents());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_decode_compressed_body() -> Result<(), ReadError> {
+        let documents: Vec<Slot> = (0..64)
+            .map(|i| document(json!({ "name": "John", "status": "active", "index": i })))
+            .map(Slot::Document)
+            .collect();
+
+        for compression in vec![CompressionStrategy::Snappy, CompressionStrategy::ZLib] {
+            let mut page = page_with_options(CollectionOptions {
+                compression: Some(compression),
+                ..Default::default()
+            });
+
+            let bytes = encode(&mut page, &documents);
+
+            assert!(bytes.len() < page.encode_body(&documents).ok().unwrap().body.len());
+            assert_eq!(decode(&page, bytes)?, documents);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_body_page_size_exceeded() {
+        let large = "x".repeat(DEFAULT_PAGE_SIZE / 2);
+        let documents = vec![
+            Slot::Document(document(json!({ "data": large }))),
+            Slot::Document(document(json!({ "data": large }))),
+        ];
+
+        let err = page(false)
+            .encode_body(&documents)
+            .err()
+            .expect("page size exceeded");
+
+        assert!(matches!(err, WriteError::PageSizeExceeded(size) if size > DEFAULT_PAGE_SIZE));
+    }
+
+    #[test]
+    fn test_encode_body_overflow() {
+        let page = page_with_options(CollectionOptions {
+            page_size: 4096,
+            ..Default::default()
+        });
+
+        let large = "x".repeat(10000);
+        let documents = vec![
+            documents().remove(0),
+            Slot::Document(document(json!({ "data": large }))),
+        ];
+
+        let encoded = page.encode_body(&documents).ok().unwrap();
+
+        assert!(encoded.body.len() <= 4096);
+        assert_eq!(encoded.overflow.len(), 1);
+        assert_eq!(encoded.overflow[0].0, 1);
+    }
+
+    #[test]
+    fn test_decode_body_page_checksum_mismatch() {
+        let mut page = page(false);
+
+        let mut bytes = encode(&mut page, &documents());
+        let last = bytes.len() - 2;
+        bytes[last] ^= 0xff;
+
+        let err = decode(&page, bytes).err().expect("checksum mismatch");
+
+        assert!(matches!(
+            err,
+            ReadError::ChecksumMismatch { collection, page: 0, offset: 0 } if collection == "users"
+        ));
+    }
+
+    #[test]
+    fn test_decode_body_record_checksum_mismatch() {
+        let mut page = page(true);
+
+        let first_record_len = documents()[0].document().unwrap().clone().write().len() + 4;
+        let second_record = 4 + 2 * SLOT_ENTRY_LEN + first_record_len;
+        let mut bytes = encode(&mut page, &documents());
+        let last = bytes.len() - 6;
+        bytes[last] ^= 0xff;
+
+        let err = decode(&page, bytes).err().expect("checksum mismatch");
+
+        assert!(matches!(
+            err,
+            ReadError::ChecksumMismatch { offset, .. } if offset == second_record
+        ));
+    }
+
+    #[test]
+    fn test_decode_legacy_body() -> Result<(), ReadError> {
+        let mut page = page(false);
+        page.metadata.version = 1;
+
+        let mut bytes = Vec::new();
+        for slot in documents() {
+            bytes.extend(slot.document().unwrap().clone().write());
+        }
+
+        assert_eq!(page.decode_body(&bytes, &page.metadata)?.0, documents());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_decode_slots() -> Result<(), ReadError> {
+        let slots = vec![
+            Slot::Tombstone,
+            Slot::Forward(RecordId::new(3, 7)),
+            Slot::Relocated {
+                origin: RecordId::new(1, 2),
+                document: document(json!({ "name": "Jack" })),
+            },
+            documents().remove(0),
+        ];
+
+        for record_checksums in vec![false, true] {
+            let mut page = page(record_checksums);
+
+            let bytes = encode(&mut page, &slots);
+
+            assert_eq!(decode(&page, bytes)?, slots);
+        }
+
+        Ok(())
     }
 }
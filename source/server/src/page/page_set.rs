@@ -1,18 +1,1015 @@
-use crate::page::page::Page;
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+use crate::io::file_descriptor::FileDescriptor;
+use crate::io::filesystem::{Filesystem, StdFs};
+use crate::io::path::DatabasePath;
+use crate::lib::uid::IntUid;
+use crate::page::buffer_pool::BufferPool;
+use crate::page::error::{ReadError, WriteError};
+use crate::page::free_space::FreeSpaceMap;
+use crate::page::page::{Page, StagedWrite, META_PAGE_EXT, OVERFLOW_PAGE_EXT};
+use crate::page::record_id::RecordId;
+use crate::page::slot::{overflow_threshold, Slot, OVERFLOW_STUB_LEN, SLOT_ENTRY_LEN};
+use crate::page::wal::{Durability, WriteAheadLog};
+use crate::storage::document::Document;
+use crate::storage::options::CollectionOptions;
+use crate::storage::utils::CollectionNameFormatter;
+
+/// The outcome of compacting a single page.
+#[derive(Default)]
+pub struct PageCompaction {
+    /// Page body bytes no longer in use.
+    pub bytes_reclaimed: usize,
+    /// Documents moved back to their original slot.
+    pub documents_moved: usize,
+    /// Pages removed from the collection.
+    pub pages_released: usize,
+}
+
+/// Statistics of a page set, from the page metadata and the sizes of the page files.
+pub struct PageSetStats {
+    pub documents: u64,
+    pub pages: usize,
+    pub pages_in_memory: usize,
+    /// Bytes of the page bodies in use before compression, from the page metadata, plus the
+    /// bytes of the records stored in overflow pages.
+    pub data_size: u64,
+    /// Bytes of every page, page metadata and overflow page file.
+    pub disk_size: u64,
+    /// Bytes of the page body files, after compression.
+    pub stored_body_size: u64,
+}
 
 /// A set of pages that represents a full or partial database collection.
+///
+/// Pages are loaded into memory when pinned and freed in least recently used order once the
+/// buffer pool is over its memory budget.
+///
+/// Each page has its own reader/writer lock, so documents can be read through a shared reference
+/// by many threads at once. Operations that change documents or the set of pages take a mutable
+/// reference, and the caller holds the collection lock for writing.
+///
+/// Documents are addressed by the [`RecordId`] of the slot they were inserted in. A document that
+/// outgrows its page is moved to another page, leaving a forwarding pointer in its original slot so
+/// its record id stays valid.
 pub struct PageSet {
-    pages: Vec<Page>,
+    collection_name: CollectionNameFormatter,
+    pages: BTreeMap<u32, RwLock<Page>>,
+    free_space: FreeSpaceMap,
+    /// Page ids in use. Ids of removed pages are reused by new pages.
+    ids: IntUid,
+    pool: Arc<BufferPool>,
+    wal: Arc<WriteAheadLog>,
+    options: Arc<CollectionOptions>,
 }
 
 impl PageSet {
-    /// Releases all pages from memory.
-    pub fn release_all(&self) {
-        todo!()
+    /// Creates an empty page set.
+    pub fn new(
+        collection_name: CollectionNameFormatter,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: Arc<CollectionOptions>,
+    ) -> Self {
+        PageSet {
+            collection_name,
+            pages: BTreeMap::new(),
+            free_space: FreeSpaceMap::new(),
+            ids: IntUid::new(),
+            pool,
+            wal,
+            options,
+        }
+    }
+
+    /// Opens every page of the collection that exists on the filesystem, without loading their
+    /// contents.
+    pub fn open(
+        collection_name: CollectionNameFormatter,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: Arc<CollectionOptions>,
+    ) -> Result<Self, ReadError> {
+        let mut set = PageSet::new(collection_name, pool, wal, options);
+
+        let prefix = format!("{}.", set.collection_name.original());
+        let files = StdFs
+            .list_files(&set.directory())
+            .map_err(|e| ReadError::Io(e))?;
+
+        for file in files {
+            let id = match file.strip_prefix(&prefix).map(|id| id.parse::<u32>()) {
+                Some(Ok(id)) => id,
+                _ => continue,
+            };
+
+            let page = Page::open(
+                set.collection_name.clone(),
+                id,
+                set.pool.clone(),
+                set.wal.clone(),
+                set.options.clone(),
+            )?;
+            set.free_space.update(id, page.free_space());
+            set.pages.insert(id, RwLock::new(page));
+        }
+
+        set.ids = IntUid::with_used(set.pages.keys().map(|id| *id as u64));
+
+        Ok(set)
+    }
+
+    /// The ids of every page in the set, in ascending order.
+    pub fn page_ids(&self) -> Vec<u32> {
+        self.pages.keys().cloned().collect()
+    }
+
+    /// The amount of pages in the set.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    /// The amount of documents stored in the set, from the page metadata.
+    pub fn document_count(&self) -> u64 {
+        self.pages
+            .values()
+            .map(|page| page.read().unwrap().metadata().count)
+            .sum()
+    }
+
+    /// Statistics of the set. Loaded pages are counted from memory, and the sizes of the page
+    /// files are read from the filesystem.
+    pub fn stats(&self) -> io::Result<PageSetStats> {
+        let mut overflow_size = 0;
+        let mut disk_size = 0;
+        let mut stored_body_size = 0;
+
+        for file in self.files()? {
+            let size = StdFs.file_size(&FileDescriptor {
+                path: DatabasePath::Data,
+                name: file.clone(),
+            })?;
+            disk_size += size;
+
+            if file.contains(&format!(".{}.", OVERFLOW_PAGE_EXT)) {
+                overflow_size += size;
+            } else if !file.ends_with(&format!(".{}", META_PAGE_EXT)) {
+                stored_body_size += size;
+            }
+        }
+
+        Ok(PageSetStats {
+            documents: self.document_count(),
+            pages: self.pages.len(),
+            pages_in_memory: self
+                .pages
+                .values()
+                .filter(|page| page.read().unwrap().is_loaded())
+                .count(),
+            data_size: self.stored_size() + overflow_size,
+            disk_size,
+            stored_body_size,
+        })
+    }
+
+    /// Names of every page, page metadata and overflow page file of the set on the filesystem.
+    pub fn files(&self) -> io::Result<Vec<String>> {
+        let directory = self.directory();
+        let files = StdFs.list_files(&directory)?;
+
+        Ok(files
+            .into_iter()
+            .filter(|file| self.collection_name.owns_file(file))
+            .map(|file| match &*directory.name {
+                "" => file,
+                dir => format!("{}/{}", dir, file),
+            })
+            .collect())
+    }
+
+    /// The directory of the page files.
+    fn directory(&self) -> FileDescriptor {
+        FileDescriptor {
+            path: DatabasePath::Data,
+            name: self.collection_name.directory().to_string(),
+        }
+    }
+
+    /// Get a page without loading it into memory. The page is locked for reading until the guard
+    /// is dropped.
+    pub fn get(&self, id: u32) -> Option<RwLockReadGuard<Page>> {
+        self.pages.get(&id).map(|page| page.read().unwrap())
+    }
+
+    /// Creates a new page with the smallest unused page id, returning its id. Pages of a capped
+    /// set always get an id larger than every other page, so page ids follow insertion order.
+    pub fn create_page(&mut self) -> Result<u32, WriteError> {
+        let id = match self.options.capped {
+            Some(_) => self.ids.increment() as u32,
+            None => self.ids.next() as u32,
+        };
+
+        let page = Page::create(
+            self.collection_name.clone(),
+            id,
+            self.pool.clone(),
+            self.wal.clone(),
+            self.options.clone(),
+        )?;
+        self.free_space.update(id, page.free_space());
+        self.pages.insert(id, RwLock::new(page));
+
+        Ok(id)
+    }
+
+    /// Free space of every page in the set.
+    pub fn free_space(&self) -> &FreeSpaceMap {
+        &self.free_space
+    }
+
+    /// Stores a document in the first page with enough free space, creating a new page if none
+    /// has enough, and returns its record id. Documents of a capped set are always stored in
+    /// the newest page.
+    pub fn insert(
+        &mut self,
+        document: Document,
+        durability: Durability,
+    ) -> Result<RecordId, WriteError> {
+        self.place(Slot::Document(document), None, durability)
+    }
+
+    /// Reads the document with a record id, following its forwarding pointer if it was moved.
+    pub fn read(&self, id: RecordId) -> Result<Option<Document>, WriteError> {
+        match self.read_slot(id)? {
+            Some(Slot::Forward(target)) => match self.read_slot(target)? {
+                Some(Slot::Relocated { document, .. }) => Ok(Some(document)),
+                _ => Ok(None),
+            },
+            Some(Slot::Document(document)) => Ok(Some(document)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads every document in the set with its record id, in record id order. Documents that
+    /// were moved to another page are listed under their original record id.
+    pub fn documents(&self) -> Result<Vec<(RecordId, Document)>, WriteError> {
+        let mut documents = Vec::new();
+
+        for id in self.page_ids() {
+            let slots = match self.read_page(id, |page| page.slots().as_ref().unwrap().to_vec())? {
+                Some(slots) => slots,
+                None => continue,
+            };
+
+            for (index, slot) in slots.into_iter().enumerate() {
+                match slot {
+                    Slot::Document(document) => {
+                        documents.push((RecordId::new(id, index as u32), document))
+                    }
+                    Slot::Relocated { origin, document } => documents.push((origin, document)),
+                    _ => {}
+                }
+            }
+        }
+
+        documents.sort_by_key(|(id, _)| *id);
+
+        Ok(documents)
+    }
+
+    /// Reads the documents in the set one page at a time, calling `f` with every document and its
+    /// record id until it returns false. Pages are read in order, and documents that were moved to
+    /// another page are visited on that page under their original record id.
+    pub fn scan<F>(&self, mut f: F) -> Result<(), WriteError>
+    where
+        F: FnMut(RecordId, Document) -> bool,
+    {
+        for id in self.page_ids() {
+            let slots = match self.read_page(id, |page| page.slots().as_ref().unwrap().to_vec())? {
+                Some(slots) => slots,
+                None => continue,
+            };
+
+            for (index, slot) in slots.into_iter().enumerate() {
+                let proceed = match slot {
+                    Slot::Document(document) => f(RecordId::new(id, index as u32), document),
+                    Slot::Relocated { origin, document } => f(origin, document),
+                    _ => true,
+                };
+
+                if !proceed {
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the document with a record id. If the new document does not fit on its page, it
+    /// is moved to another page and a forwarding pointer is left in its original slot. Returns
+    /// false if there is no document with the record id.
+    ///
+    /// Documents of a capped set are never moved, so replacing one with a document that does not
+    /// fit on its page fails.
+    pub fn replace(
+        &mut self,
+        id: RecordId,
+        document: Document,
+        durability: Durability,
+    ) -> Result<bool, WriteError> {
+        match self.read_slot(id)? {
+            Some(Slot::Document(_)) => {
+                if self.set_slot(id, Slot::Document(document.clone()), durability)? {
+                    return Ok(true);
+                }
+                if self.options.capped.is_some() {
+                    return Err(self.capped_error());
+                }
+
+                let relocated = Slot::Relocated {
+                    origin: id,
+                    document,
+                };
+                let target = self.place(relocated, Some(id.page), durability)?;
+                self.forward(id, target, durability)?;
+
+                Ok(true)
+            }
+            Some(Slot::Forward(target)) => {
+                let relocated = Slot::Relocated {
+                    origin: id,
+                    document,
+                };
+
+                if self.set_slot(target, relocated.clone(), durability)? {
+                    return Ok(true);
+                }
+
+                let new_target = self.place(relocated, Some(target.page), durability)?;
+                self.forward(id, new_target, durability)?;
+                self.set_slot(target, Slot::Tombstone, durability)?;
+
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Deletes the document with a record id, leaving a tombstone in its slot. Returns false if
+    /// there is no document with the record id. Documents of a capped set cannot be deleted.
+    pub fn delete(&mut self, id: RecordId, durability: Durability) -> Result<bool, WriteError> {
+        if self.options.capped.is_some() {
+            return Err(self.capped_error());
+        }
+
+        match self.read_slot(id)? {
+            Some(Slot::Document(_)) => self.set_slot(id, Slot::Tombstone, durability),
+            Some(Slot::Forward(target)) => {
+                self.set_slot(target, Slot::Tombstone, durability)?;
+                self.set_slot(id, Slot::Tombstone, durability)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Reads the documents stored after a record id, or from the oldest document if none is
+    /// given, in record id order and up to `limit` documents.
+    ///
+    /// Documents of a capped set are always appended, so these are the documents inserted after
+    /// the record id. Documents on pages that were dropped are skipped.
+    pub fn documents_after(
+        &self,
+        after: Option<RecordId>,
+        limit: usize,
+    ) -> Result<Vec<(RecordId, Document)>, WriteError> {
+        let mut documents = Vec::new();
+        let first_page = after.map_or(0, |id| id.page);
+
+        for id in self.page_ids().into_iter().filter(|id| *id >= first_page) {
+            let slots = match self.read_page(id, |page| page.slots().as_ref().unwrap().to_vec())? {
+                Some(slots) => slots,
+                None => continue,
+            };
+
+            for (index, slot) in slots.into_iter().enumerate() {
+                let record_id = RecordId::new(id, index as u32);
+                if after.map_or(false, |after| record_id <= after) {
+                    continue;
+                }
+
+                if let Slot::Document(document) = slot {
+                    documents.push((record_id, document));
+                    if documents.len() >= limit {
+                        return Ok(documents);
+                    }
+                }
+            }
+        }
+
+        Ok(documents)
+    }
+
+    /// The total size of the stored page bodies in bytes, from the page metadata.
+    pub fn stored_size(&self) -> u64 {
+        let page_size = self.options.page_size as u64;
+
+        self.pages
+            .values()
+            .map(|page| page_size.saturating_sub(page.read().unwrap().metadata().free))
+            .sum()
+    }
+
+    /// Drops the oldest pages of a capped set until it is within the limits of the collection,
+    /// returning the ids of the dropped pages and the amount of documents dropped. The newest
+    /// page is never dropped.
+    pub fn enforce_cap(&mut self, durability: Durability) -> Result<(Vec<u32>, u64), WriteError> {
+        let capped = match &self.options.capped {
+            Some(capped) => capped.clone(),
+            None => return Ok((Vec::new(), 0)),
+        };
+
+        let mut pages = Vec::new();
+        let mut documents = 0;
+
+        while self.pages.len() > 1 && capped.is_exceeded(self.stored_size(), self.document_count())
+        {
+            let id = *self.pages.keys().next().unwrap();
+            let (images, count) =
+                self.with_page(id, |page| Ok((page.removal()?, page.metadata().count)))?;
+
+            self.wal.write(images, durability)?;
+            self.pages.remove(&id);
+            self.free_space.remove(id);
+
+            pages.push(id);
+            documents += count;
+        }
+
+        Ok((pages, documents))
+    }
+
+    /// Compacts a single page without changing the address of any document.
+    ///
+    /// Documents that were moved off the page are moved back if the page has room for them again,
+    /// tombstones after the last slot in use are dropped and pages written with outdated storage
+    /// options are rewritten. Pages left without any slot in use are removed and their ids are
+    /// released for new pages. The page and every page a document is moved back from are swapped
+    /// in with a single write-ahead log record.
+    pub fn compact_page(&mut self, id: u32) -> Result<PageCompaction, WriteError> {
+        if !self.pages.contains_key(&id) {
+            return Ok(PageCompaction::default());
+        }
+
+        let (mut slots, size_before) = self.with_page(id, |page| {
+            Ok((page.slots().as_ref().unwrap().to_vec(), page.size()))
+        })?;
+
+        let mut changed = BTreeMap::new();
+        let mut documents_moved = 0;
+
+        for index in 0..slots.len() {
+            let target = match slots[index] {
+                Slot::Forward(target) if target.page != id => target,
+                _ => continue,
+            };
+
+            if !changed.contains_key(&target.page) {
+                let target_page = self.with_page(target.page, |page| {
+                    Ok((page.slots().as_ref().unwrap().to_vec(), page.size()))
+                })?;
+                changed.insert(target.page, target_page);
+            }
+
+            let (target_slots, _) = changed.get_mut(&target.page).unwrap();
+            let document = match target_slots.get(target.slot as usize) {
+                Some(Slot::Relocated { document, .. }) => document.clone(),
+                _ => continue,
+            };
+
+            let mut candidate = slots.clone();
+            candidate[index] = Slot::Document(document);
+
+            if self.page_mut(id).unwrap().fits(&candidate) {
+                slots = candidate;
+                target_slots[target.slot as usize] = Slot::Tombstone;
+                documents_moved += 1;
+            }
+        }
+
+        let outdated = self.page_mut(id).unwrap().is_outdated();
+        if documents_moved == 0 && !outdated && slots.last() != Some(&Slot::Tombstone) {
+            return Ok(PageCompaction::default());
+        }
+
+        changed.insert(id, (slots, size_before));
+
+        let mut images = Vec::new();
+        let mut staged: Vec<(u32, StagedWrite)> = Vec::new();
+        let mut removed = Vec::new();
+        let mut size_after = 0;
+        let mut size_before = 0;
+
+        for (page_id, (mut page_slots, size)) in changed {
+            while page_slots.last() == Some(&Slot::Tombstone) {
+                page_slots.pop();
+            }
+
+            let page = self.page_mut(page_id).unwrap();
+            size_before += size;
+
+            if page_slots.is_empty() {
+                images.extend(page.removal()?);
+                removed.push(page_id);
+            } else {
+                let mut write = page.stage(page_slots)?;
+                images.extend(write.take_images());
+                size_after += write.size();
+                staged.push((page_id, write));
+            }
+        }
+
+        self.wal.write(images, self.options.durability)?;
+
+        for (page_id, write) in staged {
+            let page = self.page_mut(page_id).unwrap();
+            page.apply(write);
+            let free = page.free_space();
+            self.free_space.update(page_id, free);
+        }
+
+        for page_id in removed.iter() {
+            self.pages.remove(page_id);
+            self.free_space.remove(*page_id);
+            self.ids.drop(*page_id as u64);
+        }
+
+        Ok(PageCompaction {
+            bytes_reclaimed: size_before.saturating_sub(size_after),
+            documents_moved,
+            pages_released: removed.len(),
+        })
     }
 
-    /// Releases the last recently used page from memory.
-    pub fn release_lru() {
-        todo!()
+    /// Pins a page, loading its contents into memory if they are not already loaded. The page
+    /// cannot be freed until it is unpinned.
+    pub fn pin(&mut self, id: u32) -> Result<&mut Page, ReadError> {
+        let pool = self.pool.clone();
+        let page = match self.page_mut(id) {
+            Some(page) => page,
+            None => return Err(ReadError::PageNotFound(id)),
+        };
+
+        if page.is_loaded() {
+            pool.record_hit();
+        } else {
+            pool.record_miss();
+            page.read()?;
+        }
+
+        page.pin();
+
+        Ok(page)
+    }
+
+    /// Unpins a page, freeing least recently used pages if the buffer pool is over its budget.
+    pub fn unpin(&mut self, id: u32) -> Result<(), WriteError> {
+        if let Some(page) = self.page_mut(id) {
+            page.unpin();
+        }
+
+        self.balance()
+    }
+
+    /// Verifies every page on the filesystem against its checksums, returning the pages that
+    /// could not be verified. Pages loaded into memory are left untouched.
+    pub fn scrub(&self) -> Vec<(u32, ReadError)> {
+        let mut damaged = Vec::new();
+
+        for (id, page) in self.pages.iter() {
+            if let Err(e) = page.read().unwrap().scrub() {
+                damaged.push((*id, e));
+            }
+        }
+
+        damaged
+    }
+
+    /// Writes every modified page to the filesystem.
+    pub fn flush_all(&mut self) -> Result<(), WriteError> {
+        for page in self.pages.values_mut() {
+            page.get_mut().unwrap().flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Releases all unpinned pages from memory.
+    pub fn release_all(&mut self) -> Result<(), WriteError> {
+        for page in self.pages.values_mut() {
+            let page = page.get_mut().unwrap();
+            if page.is_loaded() && !page.is_pinned() {
+                self.pool.record_eviction(page.is_dirty());
+                page.free()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Releases the least recently used unpinned page from memory, returning the amount of bytes
+    /// freed or None if no page could be released.
+    ///
+    /// Pages locked by another thread are skipped.
+    pub fn release_lru(&self) -> Result<Option<usize>, WriteError> {
+        let id = match self.lru() {
+            Some((id, _)) => id,
+            None => return Ok(None),
+        };
+
+        let mut page = match self.pages.get(&id).unwrap().try_write() {
+            Ok(page) => page,
+            Err(_) => return Ok(None),
+        };
+        if !page.is_loaded() || page.is_pinned() {
+            return Ok(None);
+        }
+
+        let size = page.size();
+
+        self.pool.record_eviction(page.is_dirty());
+        page.free()?;
+
+        Ok(Some(size))
+    }
+
+    /// Buffer pool time of the last access to the least recently used unpinned page.
+    pub fn lru_time(&self) -> Option<u64> {
+        self.lru().map(|(_, time)| time)
+    }
+
+    /// Places a slot in the first page with enough free space other than `exclude`, creating a
+    /// new page if none has enough.
+    fn place(
+        &mut self,
+        slot: Slot,
+        exclude: Option<u32>,
+        durability: Durability,
+    ) -> Result<RecordId, WriteError> {
+        let checksum_len = if self.options.record_checksums { 4 } else { 0 };
+        let record_len = match slot.record_len() {
+            len if len > overflow_threshold(self.options.page_size) => OVERFLOW_STUB_LEN,
+            len => len,
+        };
+        let needed = record_len + checksum_len + SLOT_ENTRY_LEN;
+
+        let free_page = match self.options.capped {
+            Some(_) => self.pages.keys().next_back().cloned().filter(|id| {
+                self.free_space
+                    .free(*id)
+                    .map_or(false, |free| free >= needed)
+            }),
+            None => self.free_space.find(needed, exclude),
+        };
+
+        if let Some(id) = free_page {
+            let inserted = self.with_page(id, |page| page.insert(slot.clone(), durability))?;
+            if let Some(index) = inserted {
+                return Ok(RecordId::new(id, index));
+            }
+        }
+
+        let id = self.create_page()?;
+        match self.with_page(id, |page| page.insert(slot, durability))? {
+            Some(index) => Ok(RecordId::new(id, index)),
+            None => Err(WriteError::PageSizeExceeded(needed)),
+        }
+    }
+
+    fn capped_error(&self) -> WriteError {
+        WriteError::CappedCollection(self.collection_name.original().clone())
+    }
+
+    /// Points a slot at the new location of its document.
+    fn forward(
+        &mut self,
+        id: RecordId,
+        target: RecordId,
+        durability: Durability,
+    ) -> Result<(), WriteError> {
+        if self.set_slot(id, Slot::Forward(target), durability)? {
+            Ok(())
+        } else {
+            Err(WriteError::PageSizeExceeded(SLOT_ENTRY_LEN))
+        }
+    }
+
+    /// Reads a slot straight from its page, without following a forwarding pointer.
+    fn read_slot(&self, id: RecordId) -> Result<Option<Slot>, WriteError> {
+        Ok(self
+            .read_page(id.page, |page| page.slot(id.slot).cloned())?
+            .flatten())
+    }
+
+    fn set_slot(
+        &mut self,
+        id: RecordId,
+        new: Slot,
+        durability: Durability,
+    ) -> Result<bool, WriteError> {
+        self.with_page(id.page, |page| page.set(id.slot, new, durability))
+    }
+
+    /// Runs an operation on a pinned page, updating the free space map afterwards.
+    fn with_page<T, F>(&mut self, id: u32, f: F) -> Result<T, WriteError>
+    where
+        F: FnOnce(&mut Page) -> Result<T, WriteError>,
+    {
+        let page = self.pin(id)?;
+        let result = f(page);
+        let free = page.free_space();
+
+        self.free_space.update(id, free);
+        self.unpin(id)?;
+
+        result
+    }
+
+    /// Runs a read on a page, loading the page into memory first if needed. Returns None if the
+    /// page does not exist.
+    ///
+    /// Loaded pages are read under a shared lock, so any amount of threads can read a page at
+    /// once. Loading a page takes its exclusive lock.
+    fn read_page<T, F>(&self, id: u32, f: F) -> Result<Option<T>, WriteError>
+    where
+        F: FnOnce(&Page) -> T,
+    {
+        let lock = match self.pages.get(&id) {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+
+        let page = lock.read().unwrap();
+        if page.is_loaded() {
+            self.pool.record_hit();
+            page.touch();
+
+            return Ok(Some(f(&page)));
+        }
+        drop(page);
+
+        let result = {
+            let mut page = lock.write().unwrap();
+            if page.is_loaded() {
+                self.pool.record_hit();
+            } else {
+                self.pool.record_miss();
+                page.read()?;
+            }
+            page.touch();
+
+            f(&page)
+        };
+
+        self.balance()?;
+
+        Ok(Some(result))
+    }
+
+    /// A page of the set, without taking its lock since the set is borrowed mutably.
+    fn page_mut(&mut self, id: u32) -> Option<&mut Page> {
+        self.pages.get_mut(&id).map(|page| page.get_mut().unwrap())
+    }
+
+    /// Frees least recently used pages until the buffer pool is within its budget or no page
+    /// can be freed.
+    fn balance(&self) -> Result<(), WriteError> {
+        while self.pool.is_over_budget() {
+            if self.release_lru()?.is_none() {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The id and last access time of the least recently used page that can be released from
+    /// memory. Pages locked by another thread are skipped.
+    fn lru(&self) -> Option<(u32, u64)> {
+        self.pages
+            .iter()
+            .filter_map(|(id, page)| {
+                let page = page.try_read().ok()?;
+                if page.is_loaded() && !page.is_pinned() {
+                    Some((*id, page.last_used()))
+                } else {
+                    None
+                }
+            })
+            .min_by_key(|(_, time)| *time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::lib::testing::with_data_dir;
+    use crate::page::wal::{DEFAULT_BATCH_INTERVAL, DEFAULT_CHECKPOINT_SIZE};
+    use crate::storage::options::CappedOptions;
+
+    /// A page size that holds three documents with 40 bytes of text.
+    const PAGE_SIZE: usize = 256;
+
+    fn page_set(options: CollectionOptions) -> PageSet {
+        PageSet::new(
+            CollectionNameFormatter::new("users"),
+            Arc::new(BufferPool::new(0)),
+            Arc::new(WriteAheadLog::new(
+                DEFAULT_CHECKPOINT_SIZE,
+                DEFAULT_BATCH_INTERVAL,
+            )),
+            Arc::new(CollectionOptions {
+                page_size: PAGE_SIZE,
+                ..options
+            }),
+        )
+    }
+
+    fn document(n: u64, text_len: usize) -> Document {
+        Document::new(
+            json!({ "n": n, "text": "x".repeat(text_len) })
+                .as_object()
+                .unwrap()
+                .clone(),
+        )
+    }
+
+    #[test]
+    fn test_replace_relocates_growing_document() -> Result<(), WriteError> {
+        with_data_dir(|| {
+            let mut set = page_set(CollectionOptions::default());
+            let ids = (0..3)
+                .map(|n| set.insert(document(n, 40), Durability::None))
+                .collect::<Result<Vec<RecordId>, WriteError>>()?;
+            assert!(ids.iter().all(|id| id.page == 0));
+
+            let id = ids[0];
+            let grown = document(0, 150);
+            assert!(set.replace(id, grown.clone(), Durability::None)?);
+
+            let target = match set.read_slot(id)? {
+                Some(Slot::Forward(target)) => target,
+                slot => panic!("expected a forwarding pointer, found {:?}", slot),
+            };
+            assert_ne!(target.page, id.page);
+            assert_eq!(
+                set.read_slot(target)?,
+                Some(Slot::Relocated {
+                    origin: id,
+                    document: grown.clone(),
+                })
+            );
+            assert_eq!(set.read(id)?, Some(grown.clone()));
+            assert!(set.documents()?.contains(&(id, grown)));
+
+            // Replacing a moved document writes it where it was moved to.
+            let regrown = document(0, 160);
+            assert!(set.replace(id, regrown.clone(), Durability::None)?);
+            assert_eq!(set.read_slot(id)?, Some(Slot::Forward(target)));
+            assert_eq!(set.read(id)?, Some(regrown));
+
+            assert!(set.delete(id, Durability::None)?);
+            assert_eq!(set.read(id)?, None);
+            assert_eq!(set.read_slot(target)?, Some(Slot::Tombstone));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_insert_reuses_deleted_slot() -> Result<(), WriteError> {
+        with_data_dir(|| {
+            let mut set = page_set(CollectionOptions::default());
+            let ids = (0..3)
+                .map(|n| set.insert(document(n, 40), Durability::None))
+                .collect::<Result<Vec<RecordId>, WriteError>>()?;
+            assert_eq!(set.len(), 1);
+
+            let free = set.free_space().free(0).unwrap();
+            assert!(set.delete(ids[1], Durability::None)?);
+            assert!(set.free_space().free(0).unwrap() > free);
+
+            let id = set.insert(document(3, 40), Durability::None)?;
+
+            assert_eq!(id, ids[1]);
+            assert_eq!(set.len(), 1);
+            assert_eq!(set.read(id)?, Some(document(3, 40)));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_compact_page() -> Result<(), WriteError> {
+        with_data_dir(|| {
+            let mut set = page_set(CollectionOptions::default());
+            let ids = (0..3)
+                .map(|n| set.insert(document(n, 40), Durability::None))
+                .collect::<Result<Vec<RecordId>, WriteError>>()?;
+
+            // Move the first document to a new page, then shrink it while it is still there.
+            let id = ids[0];
+            set.replace(id, document(0, 150), Durability::None)?;
+            set.replace(id, document(0, 10), Durability::None)?;
+            let target = match set.read_slot(id)? {
+                Some(Slot::Forward(target)) => target,
+                slot => panic!("expected a forwarding pointer, found {:?}", slot),
+            };
+            assert_eq!(set.len(), 2);
+
+            set.delete(ids[2], Durability::None)?;
+            let compaction = set.compact_page(0)?;
+
+            assert_eq!(compaction.documents_moved, 1);
+            assert_eq!(compaction.pages_released, 1);
+            assert!(compaction.bytes_reclaimed > 0);
+
+            // The document is back in its original slot.
+            assert_eq!(set.read_slot(id)?, Some(Slot::Document(document(0, 10))));
+            assert_eq!(set.read(id)?, Some(document(0, 10)));
+
+            // The tombstone at the end of the page is dropped.
+            assert_eq!(
+                set.read_slot(ids[1])?,
+                Some(Slot::Document(document(1, 40)))
+            );
+            assert_eq!(set.read_slot(ids[2])?, None);
+
+            // The emptied page is removed and its id is used by the next page.
+            assert_eq!(set.page_ids(), vec![0]);
+            assert_eq!(set.create_page()?, target.page);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_enforce_cap() -> Result<(), WriteError> {
+        let limits = [
+            CappedOptions {
+                max_size: None,
+                max_documents: Some(4),
+            },
+            CappedOptions {
+                max_size: Some(2 * PAGE_SIZE as u64),
+                max_documents: None,
+            },
+        ];
+
+        for capped in limits.iter() {
+            with_data_dir(|| -> Result<(), WriteError> {
+                let mut set = page_set(CollectionOptions {
+                    capped: Some(capped.clone()),
+                    ..CollectionOptions::default()
+                });
+
+                let mut dropped = Vec::new();
+                for n in 0..10 {
+                    set.insert(document(n, 40), Durability::None)?;
+                    dropped.extend(set.enforce_cap(Durability::None)?.0);
+                }
+
+                // The oldest pages are dropped first, and the newest page is kept.
+                let kept = set.page_ids();
+                assert!(!dropped.is_empty());
+                assert!(dropped.windows(2).all(|ids| ids[0] < ids[1]));
+                assert!(dropped.iter().all(|id| *id < kept[0]));
+                assert!(!capped.is_exceeded(set.stored_size(), set.document_count()));
+
+                // The documents left are the newest ones, in insertion order.
+                let documents = set.documents_after(None, usize::MAX)?;
+                let numbers: Vec<u64> = documents
+                    .iter()
+                    .map(|(_, document)| document.as_json()["n"].as_u64().unwrap())
+                    .collect();
+                let first = 10 - numbers.len() as u64;
+                assert_eq!(numbers, (first..10).collect::<Vec<u64>>());
+
+                // Reading after a record id on a dropped page starts at the oldest document left.
+                let after = set.documents_after(Some(RecordId::new(dropped[0], 0)), 2)?;
+                assert_eq!(after, documents[..2].to_vec());
+
+                let after = set.documents_after(Some(documents[0].0), usize::MAX)?;
+                assert_eq!(after, documents[1..].to_vec());
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
     }
 }
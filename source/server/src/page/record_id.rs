@@ -0,0 +1,56 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// The stable address of a stored document, made of the id of the page it was inserted in and
+/// the index of its slot in that page.
+///
+/// A record id stays valid for as long as the document exists, even if the document is moved to
+/// another page, so it can be kept by indexes and cursors to read the document without a scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RecordId {
+    pub page: u32,
+    pub slot: u32,
+}
+
+impl RecordId {
+    pub fn new(page: u32, slot: u32) -> Self {
+        RecordId { page, slot }
+    }
+}
+
+impl fmt::Display for RecordId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.page, self.slot)
+    }
+}
+
+impl FromStr for RecordId {
+    type Err = ();
+
+    /// Parses a record id formatted as `page:slot`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+
+        let page = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+        let slot = parts.next().ok_or(())?.parse().map_err(|_| ())?;
+
+        Ok(RecordId { page, slot })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_parse() {
+        let id = RecordId::new(16, 3);
+
+        assert_eq!(id.to_string(), "16:3");
+        assert_eq!("16:3".parse(), Ok(id));
+        assert_eq!("16".parse::<RecordId>(), Err(()));
+        assert_eq!("16:x".parse::<RecordId>(), Err(()));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::page::page::PageWriteable;
+use crate::page::record_id::RecordId;
+use crate::storage::document::Document;
+
+/// Size of a single slot directory entry.
+pub const SLOT_ENTRY_LEN: usize = 9;
+/// Size of the slot count that precedes the slot directory.
+const SLOT_COUNT_LEN: usize = 4;
+/// Size of the page id and slot index of a record location.
+const LOCATION_LEN: usize = 8;
+/// Size of the record left in place of a record stored in overflow pages.
+pub const OVERFLOW_STUB_LEN: usize = 12;
+/// The smallest page size, which holds a single slot with its record stored in overflow pages.
+pub const MIN_PAGE_SIZE: usize = SLOT_COUNT_LEN + SLOT_ENTRY_LEN + OVERFLOW_STUB_LEN + 4;
+/// Set on the slot kind of a record stored in overflow pages.
+const OVERFLOW_FLAG: u8 = 0x80;
+
+const TOMBSTONE_KIND: u8 = 0;
+const DOCUMENT_KIND: u8 = 1;
+const FORWARD_KIND: u8 = 2;
+const RELOCATED_KIND: u8 = 3;
+
+/// A single entry of a slotted page. The index of a slot never changes, so the [`RecordId`] of a
+/// document identifies it for as long as it exists.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Slot {
+    /// A deleted document. The slot can be reused by a later insert.
+    Tombstone,
+    /// A document stored in its original slot.
+    Document(Document),
+    /// A document that outgrew its page and was moved to another slot.
+    Forward(RecordId),
+    /// A document moved from its original slot, which holds a forwarding pointer to it.
+    Relocated {
+        origin: RecordId,
+        document: Document,
+    },
+}
+
+/// A slot read from a page body, before its document is decoded.
+pub enum RawSlot<'a> {
+    Tombstone,
+    Document(&'a [u8]),
+    Forward(RecordId),
+    Relocated {
+        origin: RecordId,
+        record: &'a [u8],
+    },
+    /// A record too large for a page, stored in overflow pages.
+    Overflow(OverflowStub),
+}
+
+/// Describes a record stored in a chain of overflow pages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverflowStub {
+    /// Kind of the slot the record belongs to.
+    kind: u8,
+    /// The amount of overflow pages in the chain.
+    pub chunks: u32,
+    /// Size of the record in bytes.
+    pub len: u32,
+    /// CRC32C of the record.
+    pub checksum: u32,
+}
+
+/// A slotted page body and the records that did not fit in it.
+pub struct EncodedSlots {
+    pub body: Vec<u8>,
+    /// Records stored in overflow pages, by slot index.
+    pub overflow: Vec<(u32, OverflowStub, Vec<u8>)>,
+}
+
+/// The largest record stored directly in a page body, the space left in a page holding only
+/// that record.
+pub fn overflow_threshold(page_size: usize) -> usize {
+    page_size.saturating_sub(SLOT_COUNT_LEN + SLOT_ENTRY_LEN + 4)
+}
+
+impl Slot {
+    /// The document stored in the slot, if any.
+    pub fn document(&self) -> Option<&Document> {
+        match self {
+            Slot::Document(document) => Some(document),
+            Slot::Relocated { document, .. } => Some(document),
+            _ => None,
+        }
+    }
+
+    /// Size of the encoded slot record, without its checksum.
+    pub fn record_len(&self) -> usize {
+        self.encode_record().len()
+    }
+
+    /// Encodes the slot record, without its checksum.
+    fn encode_record(&self) -> Vec<u8> {
+        match self {
+            Slot::Tombstone => Vec::new(),
+            Slot::Document(document) => document.write(),
+            Slot::Forward(target) => encode_location(*target),
+            Slot::Relocated { origin, document } => {
+                let mut record = encode_location(*origin);
+                record.extend(document.write());
+                record
+            }
+        }
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Slot::Tombstone => TOMBSTONE_KIND,
+            Slot::Document(_) => DOCUMENT_KIND,
+            Slot::Forward(_) => FORWARD_KIND,
+            Slot::Relocated { .. } => RELOCATED_KIND,
+        }
+    }
+}
+
+/// Encodes slots into a slotted page body.
+///
+/// A slotted page body is laid out as:
+/// * `u32` - The amount of slots
+/// * The slot directory, a `u8` slot kind, `u32` record offset and `u32` record length per slot
+/// * The slot records, each followed by its CRC32C if `checksums` is set
+///
+/// Document records are BSON documents. Forwarding pointers are a `u32` page id and `u32` slot
+/// index, and relocated documents are the location of their original slot followed by the BSON
+/// document. Tombstones have no record. All integers are little endian.
+///
+/// Records larger than the [`overflow_threshold`] of the page size are split into chunks of the
+/// page size and stored in overflow pages. The slot kind of such a record has the high bit set,
+/// and its record in the page body is replaced by the `u32` chunk count, `u32` record length and
+/// `u32` CRC32C of the record.
+pub fn encode_slots(slots: &[Slot], checksums: bool, page_size: usize) -> EncodedSlots {
+    let mut directory = Vec::with_capacity(SLOT_COUNT_LEN + slots.len() * SLOT_ENTRY_LEN);
+    let mut records = Vec::new();
+    let mut overflow = Vec::new();
+
+    let records_start = SLOT_COUNT_LEN + slots.len() * SLOT_ENTRY_LEN;
+
+    directory.extend(&(slots.len() as u32).to_le_bytes());
+    for (index, slot) in slots.iter().enumerate() {
+        let mut kind = slot.kind();
+        let mut record = slot.encode_record();
+
+        if record.len() > overflow_threshold(page_size) {
+            let stub = OverflowStub {
+                kind,
+                chunks: ((record.len() + page_size - 1) / page_size) as u32,
+                len: record.len() as u32,
+                checksum: crc32c::crc32c(&record),
+            };
+
+            let mut stub_record = Vec::with_capacity(OVERFLOW_STUB_LEN);
+            stub_record.extend(&stub.chunks.to_le_bytes());
+            stub_record.extend(&stub.len.to_le_bytes());
+            stub_record.extend(&stub.checksum.to_le_bytes());
+
+            overflow.push((index as u32, stub, record));
+            kind |= OVERFLOW_FLAG;
+            record = stub_record;
+        }
+
+        let offset = if record.is_empty() {
+            0
+        } else {
+            records_start + records.len()
+        };
+
+        directory.push(kind);
+        directory.extend(&(offset as u32).to_le_bytes());
+        directory.extend(&(record.len() as u32).to_le_bytes());
+
+        if !record.is_empty() {
+            let checksum = crc32c::crc32c(&record);
+            records.extend(record);
+            if checksums {
+                records.extend(&checksum.to_le_bytes());
+            }
+        }
+    }
+
+    directory.extend(records);
+
+    EncodedSlots {
+        body: directory,
+        overflow,
+    }
+}
+
+/// Splits a slotted page body into its slots. If the records are followed by checksums, each
+/// record is verified.
+///
+/// Returns the byte offset of the first damaged record on failure, or 0 if the slot directory is
+/// damaged.
+pub fn decode_slots(bytes: &[u8], checksums: bool) -> Result<Vec<RawSlot>, usize> {
+    if bytes.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let count = match bytes.get(0..SLOT_COUNT_LEN) {
+        Some(count) => read_u32(count) as usize,
+        None => return Err(0),
+    };
+
+    let checksum_len = if checksums { 4 } else { 0 };
+    let mut slots = Vec::with_capacity(count);
+
+    for i in 0..count {
+        let start = SLOT_COUNT_LEN + i * SLOT_ENTRY_LEN;
+        let entry = match bytes.get(start..start + SLOT_ENTRY_LEN) {
+            Some(entry) => entry,
+            None => return Err(0),
+        };
+
+        let kind = entry[0];
+        let offset = read_u32(&entry[1..5]) as usize;
+        let len = read_u32(&entry[5..9]) as usize;
+
+        if kind == TOMBSTONE_KIND {
+            slots.push(RawSlot::Tombstone);
+            continue;
+        }
+
+        let record = match bytes.get(offset..offset + len + checksum_len) {
+            Some(record) => record,
+            None => return Err(offset),
+        };
+        let (record, checksum) = record.split_at(len);
+
+        if checksums && read_u32(checksum) != crc32c::crc32c(record) {
+            return Err(offset);
+        }
+
+        let slot = if kind & OVERFLOW_FLAG != 0 && len == OVERFLOW_STUB_LEN {
+            RawSlot::Overflow(OverflowStub {
+                kind: kind & !OVERFLOW_FLAG,
+                chunks: read_u32(&record[0..4]),
+                len: read_u32(&record[4..8]),
+                checksum: read_u32(&record[8..12]),
+            })
+        } else {
+            match parse_record(kind, record) {
+                Some(slot) => slot,
+                None => return Err(offset),
+            }
+        };
+
+        slots.push(slot);
+    }
+
+    Ok(slots)
+}
+
+/// Parses a record reassembled from overflow pages, returning None if it is not a valid record
+/// of its slot kind.
+pub fn decode_overflow<'a>(stub: &OverflowStub, record: &'a [u8]) -> Option<RawSlot<'a>> {
+    if record.len() != stub.len as usize || crc32c::crc32c(record) != stub.checksum {
+        return None;
+    }
+
+    parse_record(stub.kind, record)
+}
+
+fn parse_record(kind: u8, record: &[u8]) -> Option<RawSlot<'_>> {
+    let len = record.len();
+
+    match kind {
+        DOCUMENT_KIND => Some(RawSlot::Document(record)),
+        FORWARD_KIND if len == LOCATION_LEN => Some(RawSlot::Forward(read_location(record))),
+        RELOCATED_KIND if len > LOCATION_LEN => Some(RawSlot::Relocated {
+            origin: read_location(record),
+            record: &record[LOCATION_LEN..],
+        }),
+        _ => None,
+    }
+}
+
+fn encode_location(id: RecordId) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(LOCATION_LEN);
+    bytes.extend(&id.page.to_le_bytes());
+    bytes.extend(&id.slot.to_le_bytes());
+    bytes
+}
+
+fn read_location(record: &[u8]) -> RecordId {
+    RecordId::new(read_u32(&record[0..4]), read_u32(&record[4..8]))
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn slots() -> Vec<Slot> {
+        let document = Document::new(json!({ "name": "John" }).as_object().unwrap().clone());
+
+        vec![
+            Slot::Document(document.clone()),
+            Slot::Tombstone,
+            Slot::Forward(RecordId::new(4, 2)),
+            Slot::Relocated {
+                origin: RecordId::new(1, 0),
+                document,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_decode_slots() {
+        let bytes = encode_slots(&slots(), true, 4096).body;
+
+        let raw_slots = decode_slots(&bytes, true).ok().unwrap();
+
+        assert_eq!(raw_slots.len(), 4);
+        assert!(matches!(raw_slots[1], RawSlot::Tombstone));
+        assert!(matches!(raw_slots[2], RawSlot::Forward(id) if id == RecordId::new(4, 2)));
+        assert!(
+            matches!(raw_slots[3], RawSlot::Relocated { origin, .. } if origin == RecordId::new(1, 0))
+        );
+    }
+
+    #[test]
+    fn test_encode_slots_overflow() {
+        let large = "x".repeat(10000);
+        let document = Document::new(json!({ "data": large }).as_object().unwrap().clone());
+        let slots = vec![Slot::Tombstone, Slot::Document(document)];
+
+        let encoded = encode_slots(&slots, false, 4096);
+
+        assert_eq!(encoded.overflow.len(), 1);
+        let (index, stub, record) = &encoded.overflow[0];
+        assert_eq!(*index, 1);
+        assert_eq!(stub.chunks, 3);
+
+        let raw_slots = decode_slots(&encoded.body, false).ok().unwrap();
+        assert!(matches!(raw_slots[1], RawSlot::Overflow(s) if s == *stub));
+        assert!(matches!(
+            decode_overflow(stub, record),
+            Some(RawSlot::Document(_))
+        ));
+        assert!(decode_overflow(stub, &record[1..]).is_none());
+    }
+
+    #[test]
+    fn test_decode_slots_damaged_directory() {
+        let bytes = encode_slots(&slots(), false, 4096).body;
+
+        let err = decode_slots(&bytes[..10], false).err();
+
+        assert_eq!(err, Some(0));
+    }
+}
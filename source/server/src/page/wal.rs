@@ -0,0 +1,582 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::io::file_descriptor::FileDescriptor;
+use crate::io::filesystem::{Filesystem, StdFs};
+use crate::io::logger::s_log;
+use crate::io::logger::EventCategory::Filesystem as FilesystemEvent;
+use crate::io::logger::EventSeverity::{Error, Info};
+use crate::io::path::DatabasePath;
+
+/// Name of the write-ahead log file in the data directory.
+pub const WAL_FILE_NAME: &str = "iris.wal";
+/// The default log size that triggers a checkpoint.
+///
+/// The standard size is 64MB.
+pub const DEFAULT_CHECKPOINT_SIZE: usize = 64E6 as usize;
+/// The default delay between flushes of the log for writes with batched durability.
+pub const DEFAULT_BATCH_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size of the length and checksum preceding each log record.
+const RECORD_HEADER_LEN: usize = 8;
+/// Image length that marks a removed file.
+const REMOVED_LEN: u32 = u32::MAX;
+/// Image length that marks a file moved from another name.
+const RENAMED_LEN: u32 = u32::MAX - 1;
+
+/// The complete new contents of a file in the data directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileImage {
+    pub name: String,
+    /// The new file contents, or None if the file is removed or renamed.
+    pub bytes: Option<Vec<u8>>,
+    /// The file moved to the name, if the file is renamed.
+    pub source: Option<String>,
+}
+
+impl FileImage {
+    /// An image that overwrites a file with the bytes.
+    pub fn new(name: String, bytes: Vec<u8>) -> Self {
+        FileImage {
+            name,
+            bytes: Some(bytes),
+            source: None,
+        }
+    }
+
+    /// An image that removes a file.
+    pub fn removed(name: String) -> Self {
+        FileImage {
+            name,
+            bytes: None,
+            source: None,
+        }
+    }
+
+    /// An image that moves a file to a new name without copying its contents through the log.
+    pub fn renamed(source: String, name: String) -> Self {
+        FileImage {
+            name,
+            bytes: None,
+            source: Some(source),
+        }
+    }
+}
+
+/// How much of a write must reach the storage device before the write is acknowledged.
+///
+/// Levels are ordered from weakest to strongest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Durability {
+    /// The log is only flushed at checkpoints. Writes survive a crash of the server, but not a
+    /// power failure.
+    None,
+    /// The log is flushed by a background thread every batch interval, so a power failure loses
+    /// at most the writes of the last interval.
+    Batched,
+    /// The log is flushed before the write is acknowledged.
+    Always,
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Durability::Always
+    }
+}
+
+/// The outcome of replaying the log at startup.
+pub struct Recovery {
+    /// The amount of log records that were replayed.
+    pub records: usize,
+    /// Bytes at the end of the log that belong to a record that was never fully written.
+    pub discarded: usize,
+}
+
+/// A redo log that makes writes to the data directory atomic.
+///
+/// Every write is appended to the log as a record of full file images and flushed to the storage
+/// device before any file is changed. If the server stops in the middle of a write, the files are
+/// restored from the log on the next startup.
+///
+/// Each record is stored as:
+/// * `u32` - Length of the record body
+/// * `u32` - CRC32C of the record body
+/// * The record body, a `u32` image count followed by each image as a length prefixed name and
+///   length prefixed contents. A contents length of `u32::MAX` marks a removed file, and a
+///   length of `u32::MAX - 1` marks a renamed file and is followed by the length prefixed name
+///   it was moved from
+///
+/// A rename is only replayed if the file it moves still exists and nothing has the new name yet,
+/// since the file is not in the log. The log is checkpointed as soon as a rename was applied, so
+/// later writes to a file with the old name are never moved by a replayed rename.
+///
+/// All integers are little endian. Once the log grows past the checkpoint size, every file that
+/// was written is flushed to the storage device and the log is truncated.
+///
+/// Writers that need the log flushed share a group commit: a single flush covers every record
+/// appended before it started, so concurrent writers wait on one flush instead of one each.
+pub struct WriteAheadLog {
+    /// The log size that triggers a checkpoint.
+    checkpoint_size: usize,
+    /// The delay between flushes for writes with batched durability.
+    batch_interval: Duration,
+    state: Mutex<LogState>,
+    /// Held while the log is being flushed, so only one writer flushes at a time.
+    flush: Mutex<()>,
+    /// Position in the log up to which every record is on the storage device.
+    flushed: AtomicU64,
+}
+
+struct LogState {
+    /// The amount of bytes appended to the log since the last checkpoint.
+    size: usize,
+    /// The amount of bytes ever appended to the log, the position of the end of the last record.
+    position: u64,
+    /// Writes that appended a record but did not finish changing their files yet.
+    writers: usize,
+    /// Files written since the last checkpoint that may not be on the storage device yet.
+    pending: BTreeSet<String>,
+    /// True if a file was renamed since the last checkpoint.
+    renamed: bool,
+    /// True if a record in the log was not completely applied, so the log must be replayed before
+    /// it is truncated.
+    unapplied: bool,
+}
+
+impl WriteAheadLog {
+    pub fn new(checkpoint_size: usize, batch_interval: Duration) -> Self {
+        WriteAheadLog {
+            checkpoint_size,
+            batch_interval,
+            state: Mutex::new(LogState {
+                size: 0,
+                position: 0,
+                writers: 0,
+                pending: BTreeSet::new(),
+                renamed: false,
+                unapplied: false,
+            }),
+            flush: Mutex::new(()),
+            flushed: AtomicU64::new(0),
+        }
+    }
+
+    /// Logs the file images, then overwrites or removes each file. The images are replayed
+    /// together, so either every file is changed or none are.
+    ///
+    /// If a file cannot be changed, the images are applied once more, skipping the renames that
+    /// were already done. If that fails too, the write fails and the record stays in the log
+    /// until a checkpoint manages to replay it, or until the log is recovered on startup.
+    ///
+    /// With [`Durability::Always`] the record is flushed before any file is changed. Files are
+    /// changed without holding the log, so writes to different files run at the same time.
+    /// Concurrent writes must not change the same files, which callers ensure by holding the lock
+    /// of the page being written.
+    pub fn write(&self, images: Vec<FileImage>, durability: Durability) -> io::Result<()> {
+        let record = encode_record(&images);
+
+        let position = {
+            let mut state = self.state.lock().unwrap();
+
+            StdFs.append(&log_file(), record.clone())?;
+            state.size += record.len();
+            state.position += record.len() as u64;
+            state.writers += 1;
+
+            state.position
+        };
+
+        let flushed = match durability {
+            Durability::Always => self.flush_to(position),
+            _ => Ok(()),
+        };
+
+        if let Err(e) = flushed {
+            self.state.lock().unwrap().writers -= 1;
+            return Err(e);
+        }
+
+        // The writer count stays raised until the files are changed, so no checkpoint truncates
+        // the log before they are.
+        let applied = images
+            .iter()
+            .try_for_each(apply)
+            .or_else(|_| images.iter().try_for_each(replay));
+
+        let mut state = self.state.lock().unwrap();
+        state.writers -= 1;
+
+        for image in images.iter() {
+            state.renamed |= image.source.is_some();
+            state.pending.insert(image.name.clone());
+        }
+
+        if let Err(e) = applied {
+            state.unapplied = true;
+            s_log(Error, FilesystemEvent, &*format!("[WAL-Apply] {}", e));
+            return Err(e);
+        }
+
+        if state.writers == 0 && (state.size >= self.checkpoint_size || state.renamed) {
+            self.checkpoint_locked(&mut state)?;
+        }
+
+        Ok(())
+    }
+
+    /// Flushes every record appended to the log so far to the storage device.
+    pub fn flush(&self) -> io::Result<()> {
+        let position = self.state.lock().unwrap().position;
+        self.flush_to(position)
+    }
+
+    /// Flushes every file written since the last checkpoint and truncates the log. If a record
+    /// was not completely applied, the log is replayed first, and kept if that fails. Nothing
+    /// happens while a write is changing its files, since its record must stay in the log.
+    pub fn checkpoint(&self) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.writers > 0 {
+            return Ok(());
+        }
+
+        self.checkpoint_locked(&mut state)
+    }
+
+    /// The delay between flushes for writes with batched durability.
+    pub fn batch_interval(&self) -> Duration {
+        self.batch_interval
+    }
+
+    /// Replays every complete record in the log, then truncates the log. A partially written
+    /// record at the end of the log is discarded, since none of its files were changed.
+    pub fn recover(&self) -> io::Result<Recovery> {
+        let mut state = self.state.lock().unwrap();
+
+        let log = log_file();
+        if !StdFs.file_exists(&log) {
+            return Ok(Recovery {
+                records: 0,
+                discarded: 0,
+            });
+        }
+
+        let recovery = replay_log(&mut state)?;
+        self.checkpoint_locked(&mut state)?;
+
+        Ok(recovery)
+    }
+
+    /// Flushes the log up to a position, unless a flush that started later already did.
+    fn flush_to(&self, position: u64) -> io::Result<()> {
+        let _flush = self.flush.lock().unwrap();
+
+        if self.flushed.load(Ordering::SeqCst) >= position {
+            return Ok(());
+        }
+
+        // Every record appended while waiting for the flush lock joins this flush.
+        let end = self.state.lock().unwrap().position;
+        StdFs.sync(&log_file())?;
+        self.flushed.fetch_max(end, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    fn checkpoint_locked(&self, state: &mut LogState) -> io::Result<()> {
+        if state.unapplied {
+            replay_log(state)?;
+            state.unapplied = false;
+        }
+
+        checkpoint(state)?;
+        self.flushed.fetch_max(state.position, Ordering::SeqCst);
+
+        Ok(())
+    }
+}
+
+/// Starts the background thread that flushes the log every batch interval, making writes with
+/// batched durability reach the storage device.
+pub fn spawn_flusher(wal: Arc<WriteAheadLog>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(wal.batch_interval());
+
+        if let Err(e) = wal.flush() {
+            s_log(Error, FilesystemEvent, &*format!("[WAL-Flush] {}", e));
+        }
+    })
+}
+
+fn apply(image: &FileImage) -> io::Result<()> {
+    let file = data_file(&image.name);
+
+    match (&image.bytes, &image.source) {
+        (Some(bytes), _) => StdFs.overwrite(&file, bytes.clone()),
+        (None, Some(source)) => StdFs.rename(&data_file(source), &file),
+        (None, None) => StdFs.remove(&file),
+    }
+}
+
+/// Applies an image of a record that may have been applied before, such as a record found in the
+/// log at startup. A rename is skipped if it was already applied.
+fn replay(image: &FileImage) -> io::Result<()> {
+    if let Some(source) = &image.source {
+        if !StdFs.file_exists(&data_file(source)) || StdFs.file_exists(&data_file(&image.name)) {
+            return Ok(());
+        }
+    }
+
+    apply(image)
+}
+
+/// Replays every complete record in the log in order.
+fn replay_log(state: &mut LogState) -> io::Result<Recovery> {
+    let bytes = StdFs.read(&log_file())?;
+    let (records, valid) = decode_records(&bytes);
+
+    for images in records.iter() {
+        for image in images {
+            replay(image)?;
+            state.pending.insert(image.name.clone());
+        }
+    }
+
+    Ok(Recovery {
+        records: records.len(),
+        discarded: bytes.len() - valid,
+    })
+}
+
+fn checkpoint(state: &mut LogState) -> io::Result<()> {
+    for name in state.pending.iter() {
+        let file = data_file(name);
+        if StdFs.file_exists(&file) {
+            StdFs.sync(&file)?;
+        }
+    }
+
+    let log = log_file();
+    StdFs.overwrite(&log, Vec::new())?;
+    StdFs.sync(&log)?;
+
+    if state.size > 0 {
+        s_log(
+            Info,
+            FilesystemEvent,
+            &*format!(
+                "[WAL-Checkpoint] files={} logBytes={}",
+                state.pending.len(),
+                state.size
+            ),
+        );
+    }
+
+    state.pending.clear();
+    state.size = 0;
+    state.renamed = false;
+
+    Ok(())
+}
+
+fn log_file() -> FileDescriptor {
+    data_file(WAL_FILE_NAME)
+}
+
+fn data_file(name: &str) -> FileDescriptor {
+    FileDescriptor {
+        path: DatabasePath::Data,
+        name: name.to_string(),
+    }
+}
+
+/// Encodes file images into a log record.
+fn encode_record(images: &[FileImage]) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    body.extend(&(images.len() as u32).to_le_bytes());
+    for image in images {
+        body.extend(&(image.name.len() as u32).to_le_bytes());
+        body.extend(image.name.as_bytes());
+        match (&image.bytes, &image.source) {
+            (Some(bytes), _) => {
+                body.extend(&(bytes.len() as u32).to_le_bytes());
+                body.extend(bytes);
+            }
+            (None, Some(source)) => {
+                body.extend(&RENAMED_LEN.to_le_bytes());
+                body.extend(&(source.len() as u32).to_le_bytes());
+                body.extend(source.as_bytes());
+            }
+            (None, None) => body.extend(&REMOVED_LEN.to_le_bytes()),
+        }
+    }
+
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN + body.len());
+    record.extend(&(body.len() as u32).to_le_bytes());
+    record.extend(&crc32c::crc32c(&body).to_le_bytes());
+    record.extend(body);
+
+    record
+}
+
+/// Decodes every complete record in the log, stopping at the first record that is truncated or
+/// does not match its checksum.
+///
+/// Returns the records and the amount of bytes they occupy.
+fn decode_records(bytes: &[u8]) -> (Vec<Vec<FileImage>>, usize) {
+    let mut records = Vec::new();
+    let mut offset = 0;
+
+    while let Some(header) = bytes.get(offset..offset + RECORD_HEADER_LEN) {
+        let len = read_u32(&header[0..4]) as usize;
+        let checksum = read_u32(&header[4..8]);
+
+        let start = offset + RECORD_HEADER_LEN;
+        let body = match bytes.get(start..start + len) {
+            Some(body) if crc32c::crc32c(body) == checksum => body,
+            _ => break,
+        };
+
+        match decode_images(body) {
+            Some(images) => records.push(images),
+            None => break,
+        }
+
+        offset = start + len;
+    }
+
+    (records, offset)
+}
+
+fn decode_images(body: &[u8]) -> Option<Vec<FileImage>> {
+    let offset = &mut 0;
+
+    let count = read_u32(take(body, offset, 4)?);
+    let mut images = Vec::new();
+
+    for _ in 0..count {
+        let name_len = read_u32(take(body, offset, 4)?) as usize;
+        let name = String::from_utf8(take(body, offset, name_len)?.to_vec()).ok()?;
+        let image = match read_u32(take(body, offset, 4)?) {
+            REMOVED_LEN => FileImage::removed(name),
+            RENAMED_LEN => {
+                let source_len = read_u32(take(body, offset, 4)?) as usize;
+                let source = String::from_utf8(take(body, offset, source_len)?.to_vec()).ok()?;
+                FileImage::renamed(source, name)
+            }
+            len => FileImage::new(name, take(body, offset, len as usize)?.to_vec()),
+        };
+
+        images.push(image);
+    }
+
+    Some(images)
+}
+
+/// Takes the next `len` bytes of a record body, advancing the offset.
+fn take<'a>(body: &'a [u8], offset: &mut usize, len: usize) -> Option<&'a [u8]> {
+    let slice = body.get(*offset..*offset + len)?;
+    *offset += len;
+    Some(slice)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::testing::with_data_dir;
+
+    fn images() -> Vec<FileImage> {
+        vec![
+            FileImage::new("users.0".to_string(), vec![1, 2, 3]),
+            FileImage::new("users.0.meta".to_string(), b"IRISPAGE VERSION=1".to_vec()),
+            FileImage::removed("users.1".to_string()),
+            FileImage::renamed("users.2".to_string(), "people.2".to_string()),
+        ]
+    }
+
+    #[test]
+    fn test_encode_decode_records() {
+        let mut log = encode_record(&images());
+        log.extend(encode_record(&[]));
+
+        let (records, valid) = decode_records(&log);
+
+        assert_eq!(records, vec![images(), vec![]]);
+        assert_eq!(valid, log.len());
+    }
+
+    #[test]
+    fn test_decode_torn_record() {
+        let complete = encode_record(&images());
+        let mut log = complete.clone();
+        let torn = encode_record(&images());
+        log.extend(&torn[..torn.len() - 1]);
+
+        let (records, valid) = decode_records(&log);
+
+        assert_eq!(records, vec![images()]);
+        assert_eq!(valid, complete.len());
+    }
+
+    #[test]
+    fn test_durability_order() {
+        let level: Durability = serde_json::from_str("\"batched\"").unwrap();
+
+        assert_eq!(level, Durability::Batched);
+        assert!(Durability::None < Durability::Batched);
+        assert_eq!(level.max(Durability::Always), Durability::Always);
+    }
+
+    #[test]
+    fn test_replay_unapplied_record() {
+        with_data_dir(|| {
+            let wal = WriteAheadLog::new(usize::MAX, DEFAULT_BATCH_INTERVAL);
+            let images = vec![
+                FileImage::new("users.0".to_string(), vec![1]),
+                FileImage::new("missing/users.1".to_string(), vec![2]),
+                FileImage::new("users.2".to_string(), vec![3]),
+            ];
+
+            assert!(wal.write(images, Durability::Always).is_err());
+            assert_eq!(StdFs.read(&data_file("users.0")).unwrap(), vec![1]);
+            assert!(!StdFs.file_exists(&data_file("users.2")));
+
+            // The record is kept until it can be replayed.
+            assert!(wal.checkpoint().is_err());
+            assert!(!StdFs.read(&log_file()).unwrap().is_empty());
+
+            StdFs
+                .create_dir(DatabasePath::Data.file("missing"))
+                .unwrap();
+            wal.checkpoint().unwrap();
+
+            assert_eq!(StdFs.read(&data_file("missing/users.1")).unwrap(), vec![2]);
+            assert_eq!(StdFs.read(&data_file("users.2")).unwrap(), vec![3]);
+            assert!(StdFs.read(&log_file()).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_decode_damaged_record() {
+        let mut log = encode_record(&images());
+        let last = log.len() - 1;
+        log[last] ^= 0xFF;
+
+        let (records, valid) = decode_records(&log);
+
+        assert!(records.is_empty());
+        assert_eq!(valid, 0);
+    }
+}
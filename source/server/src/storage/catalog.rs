@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::io::file_descriptor::FileDescriptor;
+use crate::io::filesystem::{Filesystem, StdFs};
+use crate::io::path::DatabasePath;
+use crate::page::error::ReadError;
+use crate::page::wal::{Durability, FileImage, WriteAheadLog};
+use crate::storage::options::CollectionOptions;
+use crate::storage::view::ViewEntry;
+
+/// Name of the catalog file in the data directory.
+pub const CATALOG_FILE_NAME: &str = "iris.catalog";
+/// The catalog format version written by this build.
+///
+/// Version 2 catalogs record views, which builds that only know version 1 would drop when
+/// rewriting the catalog.
+pub const CATALOG_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The catalog record of a single collection.
+pub struct CatalogEntry {
+    pub name: String,
+    pub options: CollectionOptions,
+    /// The amount of pages of the collection when the catalog was last written.
+    pub pages: usize,
+    /// When the collection was created, in seconds since the unix epoch.
+    pub created: i64,
+}
+
+impl CatalogEntry {
+    /// A record for a collection created now.
+    pub fn new(name: String, options: CollectionOptions) -> Self {
+        CatalogEntry {
+            name,
+            options,
+            pages: 0,
+            created: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CatalogFile {
+    version: u32,
+    collections: Vec<CatalogEntry>,
+    #[serde(default)]
+    views: Vec<ViewEntry>,
+}
+
+#[derive(Debug)]
+/// Error that occurs when loading the catalog or the collections it records.
+pub enum CatalogError {
+    /// Io error.
+    Io(io::Error),
+    /// The catalog file is not valid JSON of the catalog format.
+    Malformed(serde_json::Error),
+    /// The catalog was written by a newer build.
+    UnsupportedVersion(u32),
+    /// A collection recorded in the catalog could not be opened.
+    Collection { name: String, error: ReadError },
+}
+
+impl From<io::Error> for CatalogError {
+    fn from(e: io::Error) -> Self {
+        CatalogError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for CatalogError {
+    fn from(e: serde_json::Error) -> Self {
+        CatalogError::Malformed(e)
+    }
+}
+
+/// The list of collections and views in a database, stored as a JSON file in the directory of
+/// the database.
+///
+/// The catalog is rewritten as a whole through the write-ahead log whenever it changes, so a
+/// crash leaves either the old or the new catalog on the filesystem.
+pub struct Catalog {
+    /// The directory of the database, relative to the data directory.
+    directory: String,
+    entries: BTreeMap<String, CatalogEntry>,
+    views: BTreeMap<String, ViewEntry>,
+}
+
+impl Catalog {
+    pub fn new(directory: String) -> Self {
+        Catalog {
+            directory,
+            entries: BTreeMap::new(),
+            views: BTreeMap::new(),
+        }
+    }
+
+    /// Loads the catalog of a database directory. A database without a catalog file has no
+    /// collections.
+    pub fn load(directory: String) -> Result<Self, CatalogError> {
+        let mut catalog = Catalog::new(directory);
+
+        let file = catalog.file();
+        if StdFs.file_exists(&file) {
+            catalog.decode(&StdFs.read(&file)?)?;
+        }
+
+        Ok(catalog)
+    }
+
+    /// Writes the catalog to the data directory.
+    pub fn save(&self, wal: &WriteAheadLog) -> io::Result<()> {
+        wal.write(vec![self.image()], Durability::Always)
+    }
+
+    /// A file image of the catalog, for writing the catalog together with other files.
+    pub fn image(&self) -> FileImage {
+        FileImage::new(self.file().name, self.encode())
+    }
+
+    /// A file image that removes the catalog.
+    pub fn removal(&self) -> FileImage {
+        FileImage::removed(self.file().name)
+    }
+
+    /// Every collection in the catalog, by name.
+    pub fn entries(&self) -> &BTreeMap<String, CatalogEntry> {
+        &self.entries
+    }
+
+    pub fn get(&self, name: &str) -> Option<&CatalogEntry> {
+        self.entries.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut CatalogEntry> {
+        self.entries.get_mut(name)
+    }
+
+    /// Adds or replaces the record of a collection.
+    pub fn put(&mut self, entry: CatalogEntry) {
+        self.entries.insert(entry.name.clone(), entry);
+    }
+
+    /// Removes the record of a collection, returning it if it existed.
+    pub fn remove(&mut self, name: &str) -> Option<CatalogEntry> {
+        self.entries.remove(name)
+    }
+
+    /// Every view in the catalog, by name.
+    pub fn views(&self) -> &BTreeMap<String, ViewEntry> {
+        &self.views
+    }
+
+    pub fn view(&self, name: &str) -> Option<&ViewEntry> {
+        self.views.get(name)
+    }
+
+    /// Adds or replaces the record of a view.
+    pub fn put_view(&mut self, view: ViewEntry) {
+        self.views.insert(view.name.clone(), view);
+    }
+
+    /// Removes the record of a view, returning it if it existed.
+    pub fn remove_view(&mut self, name: &str) -> Option<ViewEntry> {
+        self.views.remove(name)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let file = CatalogFile {
+            version: CATALOG_VERSION,
+            collections: self.entries.values().cloned().collect(),
+            views: self.views.values().cloned().collect(),
+        };
+
+        serde_json::to_vec_pretty(&file).unwrap()
+    }
+
+    /// Replaces the entries with the ones of an encoded catalog.
+    fn decode(&mut self, bytes: &[u8]) -> Result<(), CatalogError> {
+        let file: CatalogFile = serde_json::from_slice(bytes)?;
+
+        if file.version > CATALOG_VERSION {
+            return Err(CatalogError::UnsupportedVersion(file.version));
+        }
+
+        self.entries.clear();
+        for entry in file.collections {
+            self.put(entry);
+        }
+
+        self.views.clear();
+        for view in file.views {
+            self.put_view(view);
+        }
+
+        Ok(())
+    }
+
+    fn file(&self) -> FileDescriptor {
+        FileDescriptor {
+            path: DatabasePath::Data,
+            name: format!("{}/{}", self.directory, CATALOG_FILE_NAME),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() -> Result<(), CatalogError> {
+        let mut catalog = Catalog::new("shop".to_string());
+        catalog.put(CatalogEntry::new(
+            "users".to_string(),
+            CollectionOptions::default(),
+        ));
+        catalog.get_mut("users").unwrap().pages = 3;
+        catalog.put_view(ViewEntry::new(
+            "active".to_string(),
+            serde_json::from_str(r#"{ "source": "users", "query": { "active": true } }"#)?,
+        ));
+
+        let mut decoded = Catalog::new("shop".to_string());
+        decoded.decode(&catalog.encode())?;
+
+        assert_eq!(decoded.entries().len(), 1);
+        assert_eq!(decoded.get("users").unwrap().pages, 3);
+        assert_eq!(decoded.view("active").unwrap().definition.source, "users");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_newer_version() {
+        let bytes = br#"{ "version": 99, "collections": [] }"#;
+
+        let err = Catalog::new("shop".to_string()).decode(bytes).err();
+
+        assert!(matches!(err, Some(CatalogError::UnsupportedVersion(99))));
+    }
+}
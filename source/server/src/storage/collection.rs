@@ -1,33 +1,667 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 
-use crate::api::collection_action::CollectionAction;
+use crate::api::collection_action::{
+    CollectionAction, CollectionActionContext, CollectionReadAction, CollectionReadContext,
+};
+use crate::io::logger::s_log;
+use crate::io::logger::EventCategory::Filesystem;
+use crate::io::logger::EventSeverity::{Error, Info, Warn};
+use crate::page::buffer_pool::BufferPool;
+use crate::page::error::{ReadError, WriteError};
 use crate::page::page_set::PageSet;
+use crate::page::record_id::RecordId;
+use crate::page::wal::{Durability, WriteAheadLog};
+use crate::storage::compaction::{Compaction, CompactionProgress};
+use crate::storage::document::Document;
+use crate::storage::history::History;
+use crate::storage::ids::{IdIndex, ID_FIELD};
+use crate::storage::options::{CollectionOptions, ValidationMode};
+use crate::storage::query::Filter;
+use crate::storage::tail::InsertNotifier;
+use crate::storage::ttl;
+use crate::storage::update::Update;
 use crate::storage::utils::CollectionNameFormatter;
+use crate::storage::view::ViewDefinition;
 use serde::de::DeserializeOwned;
+use serde_json::Value;
 
 /// An abstraction over data pages.
 pub struct Collection {
     name: CollectionNameFormatter,
+    options: Arc<CollectionOptions>,
     pages: PageSet,
+    compaction: Compaction,
+    /// True once the collection was dropped or renamed. Requests that still hold the collection
+    /// can no longer write to it.
+    closed: bool,
+    /// Wakes tailable reads waiting for new documents.
+    inserts: Arc<InsertNotifier>,
+    /// The `_id` of every document, built when the collection is first written to.
+    ids: Option<IdIndex>,
+    /// The earlier versions of every document, if the collection keeps a history.
+    history: Option<History>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// Storage statistics of a collection, from its page metadata and page files.
+pub struct CollectionStats {
+    pub name: String,
+    pub documents: u64,
+    /// Bytes of the stored documents before compression, including the slot directory of every
+    /// page.
+    pub data_size: u64,
+    pub average_document_size: u64,
+    pub pages: usize,
+    pub pages_in_memory: usize,
+    /// Bytes of every file of the collection, including its history.
+    pub disk_size: u64,
+    /// The size of the page bodies before compression divided by their size on the
+    /// filesystem.
+    pub compression_ratio: f64,
+    /// The approximate memory used by each index in bytes, by the name of the indexed field.
+    /// The `_id` index is only built once the collection is written to.
+    pub index_sizes: BTreeMap<String, usize>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// The result of verifying every page of a collection.
+pub struct ScrubReport {
+    /// The amount of pages that were verified.
+    pub pages_checked: usize,
+    /// Pages that failed verification.
+    pub damaged: Vec<PageDamage>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A page that failed verification.
+pub struct PageDamage {
+    pub page: u32,
+    /// Byte offset of the damage in the page body, if known.
+    pub offset: Option<usize>,
+    pub reason: String,
 }
 
 impl Collection {
-    pub fn new(name: CollectionNameFormatter) -> Self {
-        todo!()
+    /// Creates a collection without any pages.
+    pub fn new(
+        name: CollectionNameFormatter,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: CollectionOptions,
+    ) -> Self {
+        let options = Arc::new(options);
+
+        Collection {
+            history: options.history.as_ref().map(|history| {
+                let pages =
+                    PageSet::new(name.history(), pool.clone(), wal.clone(), options.clone());
+                History::new(pages, history)
+            }),
+            pages: PageSet::new(name.clone(), pool, wal, options.clone()),
+            name,
+            options,
+            compaction: Compaction::new(),
+            closed: false,
+            inserts: Arc::new(InsertNotifier::new()),
+            ids: None,
+        }
+    }
+
+    /// Opens a collection from the pages on the filesystem.
+    pub fn open(
+        name: CollectionNameFormatter,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+        options: CollectionOptions,
+    ) -> Result<Self, ReadError> {
+        let options = Arc::new(options);
+
+        let history = match &options.history {
+            Some(history) => {
+                let pages =
+                    PageSet::open(name.history(), pool.clone(), wal.clone(), options.clone())?;
+                Some(History::new(pages, history))
+            }
+            None => None,
+        };
+
+        Ok(Collection {
+            history,
+            pages: PageSet::open(name.clone(), pool, wal, options.clone())?,
+            name,
+            options,
+            compaction: Compaction::new(),
+            closed: false,
+            inserts: Arc::new(InsertNotifier::new()),
+            ids: None,
+        })
     }
 
     pub fn name(&self) -> &CollectionNameFormatter {
         &self.name
     }
 
+    pub fn options(&self) -> &CollectionOptions {
+        &self.options
+    }
+
+    pub fn pages(&self) -> &PageSet {
+        &self.pages
+    }
+
+    pub fn pages_mut(&mut self) -> &mut PageSet {
+        &mut self.pages
+    }
+
+    /// The version history of the collection, if it keeps one.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Names of every file of the collection on the filesystem, including its history.
+    pub fn files(&self) -> io::Result<Vec<String>> {
+        let mut files = self.pages.files()?;
+        if let Some(history) = &self.history {
+            files.extend(history.pages().files()?);
+        }
+
+        Ok(files)
+    }
+
+    /// Buffer pool time of the last access to the least recently used unpinned page of the
+    /// collection or its history.
+    pub fn lru_time(&self) -> Option<u64> {
+        let history = self.history.as_ref().and_then(|h| h.pages().lru_time());
+
+        match (self.pages.lru_time(), history) {
+            (Some(pages), Some(history)) => Some(pages.min(history)),
+            (pages, history) => pages.or(history),
+        }
+    }
+
+    /// Releases the least recently used unpinned page of the collection or its history from
+    /// memory, see [`PageSet::release_lru`].
+    pub fn release_lru(&self) -> Result<Option<usize>, WriteError> {
+        if let Some(history) = &self.history {
+            if let Some(time) = history.pages().lru_time() {
+                if self.pages.lru_time().map_or(true, |pages| time < pages) {
+                    return history.pages().release_lru();
+                }
+            }
+        }
+
+        self.pages.release_lru()
+    }
+
+    /// True once the collection was dropped or renamed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn insert_notifier(&self) -> &Arc<InsertNotifier> {
+        &self.inserts
+    }
+
+    /// The durability applied to a write that requested a level. Writes are never less durable
+    /// than the collection's durability option.
+    pub fn durability(&self, requested: Option<Durability>) -> Durability {
+        match requested {
+            Some(durability) => durability.max(self.options.durability),
+            None => self.options.durability,
+        }
+    }
+
+    /// Stores documents, returning the record id and `_id` of each document and the durability
+    /// applied to the write. The oldest pages of a capped collection are dropped once the
+    /// collection is over its limits.
+    ///
+    /// Documents without an `_id` are given one. Every document is checked before any is
+    /// stored, so a duplicate `_id` or a schema violation stores none of the documents.
+    pub fn insert_many(
+        &mut self,
+        mut documents: Vec<Document>,
+        durability: Option<Durability>,
+    ) -> Result<(Vec<(RecordId, Value)>, Durability), WriteError> {
+        if self.closed {
+            return Err(WriteError::CollectionClosed(self.name.original().clone()));
+        }
+
+        let mut ids = Vec::with_capacity(documents.len());
+        for document in documents.iter_mut() {
+            let id = self.id_index()?.assign(document.as_json_mut(), &ids)?;
+            self.validate(document)?;
+            ids.push(id);
+        }
+
+        let durability = self.durability(durability);
+
+        let mut versions = Vec::new();
+        if self.history.is_some() {
+            for (document, id) in documents.iter().zip(ids.iter()) {
+                versions.push((id.clone(), Some(document.as_json().clone())));
+            }
+        }
+
+        let mut inserted = Vec::with_capacity(documents.len());
+        for (document, id) in documents.into_iter().zip(ids) {
+            let record = self.pages.insert(document, durability)?;
+            self.id_index()?.insert(&id, record);
+            inserted.push((record, id));
+        }
+
+        if let Some(history) = &mut self.history {
+            history.record(versions, Utc::now(), durability)?;
+        }
+
+        let (pages, documents) = self.pages.enforce_cap(durability)?;
+        if !pages.is_empty() {
+            self.id_index()?.remove_pages(&pages);
+
+            s_log(
+                Info,
+                Filesystem,
+                &*format!(
+                    "[Capped-Drop] collection={} pages={} documents={}",
+                    self.name.original(),
+                    pages.len(),
+                    documents
+                ),
+            );
+        }
+
+        self.inserts.notify();
+
+        Ok((inserted, durability))
+    }
+
+    /// Updates the first document that matches a filter, or every matching document if `many`
+    /// is true. Returns the amount of documents matched and changed by the update, and the
+    /// durability applied to the write.
+    ///
+    /// Every document is updated and checked before any is stored, so an update that does not
+    /// apply or a schema violation stores none of the documents. Documents that grow past the
+    /// free space of their page are moved to another page. Documents of a capped collection are
+    /// never moved, so such an update fails once the documents before it are stored. The history
+    /// version of each document is recorded as soon as it is stored, so the history matches the
+    /// documents stored before a failure.
+    pub fn update(
+        &mut self,
+        filter: &Filter,
+        update: &Update,
+        many: bool,
+        durability: Option<Durability>,
+    ) -> Result<(u64, u64, Durability), WriteError> {
+        if self.closed {
+            return Err(WriteError::CollectionClosed(self.name.original().clone()));
+        }
+
+        let now = Utc::now();
+        let mut matched: Vec<(RecordId, Document)> = Vec::new();
+        self.pages.scan(|record, document| {
+            if filter.matches(document.as_json()) {
+                matched.push((record, document));
+            }
+            many || matched.is_empty()
+        })?;
+
+        let mut updated = Vec::new();
+        for (record, document) in matched.iter() {
+            let changed = update
+                .apply(document.as_json().clone(), now)
+                .map(Document::new)
+                .map_err(WriteError::InvalidUpdate)?;
+
+            if changed != *document {
+                self.validate(&changed)?;
+                updated.push((*record, changed));
+            }
+        }
+
+        let durability = self.durability(durability);
+
+        for (record, document) in updated.iter() {
+            if !self.pages.replace(*record, document.clone(), durability)? {
+                continue;
+            }
+
+            if let (Some(history), Some(id)) = (&mut self.history, document.as_json().get(ID_FIELD))
+            {
+                history.record(
+                    vec![(id.clone(), Some(document.as_json().clone()))],
+                    now,
+                    durability,
+                )?;
+            }
+        }
+
+        Ok((matched.len() as u64, updated.len() as u64, durability))
+    }
+
+    /// The `_id` index of the collection, built from the stored documents on first use.
+    fn id_index(&mut self) -> Result<&mut IdIndex, WriteError> {
+        if self.ids.is_none() {
+            let documents = self.pages.documents()?;
+            self.ids = Some(IdIndex::build(self.options.id_generator, &documents));
+        }
+
+        Ok(self.ids.as_mut().unwrap())
+    }
+
+    /// Record ids of the documents that expired at `now` according to the TTL of the collection.
+    pub fn expired_documents(&self, now: DateTime<Utc>) -> Result<Vec<RecordId>, WriteError> {
+        let ttl = match &self.options.ttl {
+            Some(ttl) => ttl,
+            None => return Ok(Vec::new()),
+        };
+
+        Ok(self
+            .pages
+            .documents()?
+            .into_iter()
+            .filter(|(_, document)| ttl::is_expired(ttl, document.as_json(), now))
+            .map(|(id, _)| id)
+            .collect())
+    }
+
+    /// Deletes the documents with the record ids that are still expired at `now`, returning the
+    /// amount of documents deleted. Documents changed since they were found are kept if they no
+    /// longer expired.
+    pub fn delete_expired(
+        &mut self,
+        ids: Vec<RecordId>,
+        now: DateTime<Utc>,
+    ) -> Result<u64, WriteError> {
+        if self.closed {
+            return Err(WriteError::CollectionClosed(self.name.original().clone()));
+        }
+
+        let ttl = match &self.options.ttl {
+            Some(ttl) => ttl.clone(),
+            None => return Ok(0),
+        };
+        let durability = self.durability(None);
+
+        let mut deleted = 0;
+        let mut versions = Vec::new();
+        for id in ids {
+            let document = match self.pages.read(id)? {
+                Some(document) if ttl::is_expired(&ttl, document.as_json(), now) => document,
+                _ => continue,
+            };
+
+            if self.pages.delete(id, durability)? {
+                if let Some(id) = document.as_json().get(ID_FIELD) {
+                    if let Some(ids) = &mut self.ids {
+                        ids.remove(id);
+                    }
+                    versions.push((id.clone(), None));
+                }
+                deleted += 1;
+            }
+        }
+
+        if let Some(history) = &mut self.history {
+            history.record(versions, Utc::now(), durability)?;
+        }
+
+        Ok(deleted)
+    }
+
+    /// Deletes the versions of the history that fell out of its retention at `now`, returning the
+    /// amount of versions deleted.
+    pub fn prune_history(&mut self, now: DateTime<Utc>) -> Result<u64, WriteError> {
+        if self.closed {
+            return Err(WriteError::CollectionClosed(self.name.original().clone()));
+        }
+
+        let durability = self.durability(None);
+        match &mut self.history {
+            Some(history) => history.prune(now, durability),
+            None => Ok(0),
+        }
+    }
+
+    /// Validates a document against the schema of the collection before it is written. Documents
+    /// that do not match are rejected in strict mode and logged in warn mode.
+    pub fn validate(&self, document: &Document) -> Result<(), WriteError> {
+        let schema = match &self.options.schema {
+            Some(schema) if self.options.validation != ValidationMode::Off => schema,
+            _ => return Ok(()),
+        };
+
+        let violation = match schema.validate(&Value::Object(document.as_json().clone())) {
+            Ok(()) => return Ok(()),
+            Err(violation) => violation,
+        };
+
+        if self.options.validation == ValidationMode::Strict {
+            return Err(WriteError::SchemaViolation(violation));
+        }
+
+        s_log(
+            Warn,
+            Filesystem,
+            &*format!(
+                "[Schema-Violation] collection={} path={} {}",
+                self.name.original(),
+                violation.path,
+                violation.msg
+            ),
+        );
+
+        Ok(())
+    }
+
+    /// Releases every page from memory and stops any compaction, so the collection files can be
+    /// removed or renamed. Writes to the collection fail from then on.
+    pub fn close(&mut self) -> Result<(), WriteError> {
+        self.closed = true;
+        self.compaction.abort();
+        self.inserts.notify();
+
+        if let Some(history) = &mut self.history {
+            history.close()?;
+        }
+
+        self.pages.flush_all()?;
+        self.pages.release_all()
+    }
+
+    /// Allows writes to a closed collection again, for when dropping or renaming it failed. Its
+    /// pages are read back into memory as they are used.
+    pub fn reopen(&mut self) {
+        self.closed = false;
+    }
+
+    /// Storage statistics of the collection.
+    pub fn stats(&self) -> io::Result<CollectionStats> {
+        let pages = self.pages.stats()?;
+
+        let mut disk_size = pages.disk_size;
+        if let Some(history) = &self.history {
+            disk_size += history.pages().stats()?.disk_size;
+        }
+
+        let body_size = self.pages.stored_size();
+        let compression_ratio = match pages.stored_body_size {
+            0 => 1.0,
+            stored => body_size as f64 / stored as f64,
+        };
+
+        let mut index_sizes = BTreeMap::new();
+        index_sizes.insert(
+            ID_FIELD.to_string(),
+            self.ids.as_ref().map_or(0, |ids| ids.size()),
+        );
+
+        Ok(CollectionStats {
+            name: self.name.original().clone(),
+            documents: pages.documents,
+            data_size: pages.data_size,
+            average_document_size: pages.data_size.checked_div(pages.documents).unwrap_or(0),
+            pages: pages.pages,
+            pages_in_memory: pages.pages_in_memory,
+            disk_size,
+            compression_ratio,
+            index_sizes,
+        })
+    }
+
+    /// Verifies every page of the collection against its checksums. Damaged pages are logged
+    /// and reported rather than failing the scrub.
+    pub fn scrub(&self) -> ScrubReport {
+        let damaged: Vec<PageDamage> = self
+            .pages
+            .scrub()
+            .into_iter()
+            .map(|(page, e)| {
+                s_log(
+                    Error,
+                    Filesystem,
+                    &*format!(
+                        "[Scrub-Damage] collection={} page={} {:?}",
+                        self.name.original(),
+                        page,
+                        e
+                    ),
+                );
+
+                let offset = match e {
+                    ReadError::ChecksumMismatch { offset, .. } => Some(offset),
+                    _ => None,
+                };
+
+                PageDamage {
+                    page,
+                    offset,
+                    reason: format!("{:?}", e),
+                }
+            })
+            .collect();
+
+        ScrubReport {
+            pages_checked: self.pages.len(),
+            damaged,
+        }
+    }
+
+    /// Progress of the current or last compaction of the collection.
+    pub fn compaction(&self) -> &CompactionProgress {
+        self.compaction.progress()
+    }
+
+    /// Starts compacting every page of the collection. The pages are compacted by the
+    /// compaction worker one at a time. Returns false if a compaction is already running.
+    pub fn start_compaction(&mut self) -> bool {
+        let page_ids = self.pages.page_ids();
+        let pages = page_ids.len();
+
+        if !self.compaction.start(page_ids) {
+            return false;
+        }
+
+        s_log(
+            Info,
+            Filesystem,
+            &*format!(
+                "[Compaction-Start] collection={} pages={}",
+                self.name.original(),
+                pages
+            ),
+        );
+
+        true
+    }
+
+    /// Compacts the next page waiting for compaction. Returns true if more pages are waiting.
+    pub fn compact_next(&mut self) -> Result<bool, WriteError> {
+        let id = match self.compaction.next_page() {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        let page = match self.pages.compact_page(id) {
+            Ok(page) => page,
+            Err(e) => {
+                s_log(
+                    Error,
+                    Filesystem,
+                    &*format!(
+                        "[Compaction-Failed] collection={} page={} {:?}",
+                        self.name.original(),
+                        id,
+                        e
+                    ),
+                );
+                self.compaction.abort();
+                return Err(e);
+            }
+        };
+
+        self.compaction.record(page);
+
+        let progress = self.compaction.progress();
+        s_log(
+            Info,
+            Filesystem,
+            &*format!(
+                "[Compaction-Progress] collection={} page={} compacted={}/{} reclaimed={} bytes",
+                self.name.original(),
+                id,
+                progress.pages_compacted,
+                progress.pages_total,
+                progress.bytes_reclaimed
+            ),
+        );
+
+        if !progress.running {
+            s_log(
+                Info,
+                Filesystem,
+                &*format!(
+                    "[Compaction-Finish] collection={} reclaimed={} bytes moved={} released={}",
+                    self.name.original(),
+                    progress.bytes_reclaimed,
+                    progress.documents_moved,
+                    progress.pages_released
+                ),
+            );
+        }
+
+        Ok(progress.running)
+    }
+
     /// Dispatches a collection action, returning the output.
-    pub fn dispatch_action<I, O, A>(self, action: A)
+    pub fn dispatch_action<I, O, A>(&mut self, action: &A, input: I) -> Result<O, WriteError>
     where
         I: DeserializeOwned,
         O: Serialize,
         A: CollectionAction<I, O>,
     {
-        todo!()
-        // action.handle();
+        action.handle(CollectionActionContext::new(input, self))
+    }
+
+    /// Dispatches a collection read action, returning the output. The view is set if the action
+    /// was dispatched on a view of the collection.
+    pub fn dispatch_read_action<I, O, A>(
+        &self,
+        action: &A,
+        input: I,
+        view: Option<&ViewDefinition>,
+    ) -> Result<O, WriteError>
+    where
+        I: DeserializeOwned,
+        O: Serialize,
+        A: CollectionReadAction<I, O>,
+    {
+        action.handle(CollectionReadContext::new(input, self, view))
     }
 }
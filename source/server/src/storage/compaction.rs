@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::io::logger::s_log;
+use crate::io::logger::EventCategory::Filesystem;
+use crate::io::logger::EventSeverity::Error;
+use crate::page::page_set::PageCompaction;
+use crate::storage::registry::DatabaseRegistry;
+
+/// Delay between compaction steps, leaving the database free for other requests in between.
+pub const COMPACTION_STEP_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Serialize, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+/// Progress of the compaction of a collection.
+pub struct CompactionProgress {
+    /// True while pages are still waiting to be compacted.
+    pub running: bool,
+    /// The amount of pages in the collection when the compaction started.
+    pub pages_total: usize,
+    /// The amount of pages compacted so far.
+    pub pages_compacted: usize,
+    /// Page body bytes no longer in use.
+    pub bytes_reclaimed: usize,
+    /// Documents moved back to their original slot.
+    pub documents_moved: usize,
+    /// Pages removed from the collection.
+    pub pages_released: usize,
+}
+
+/// A compaction job that compacts a collection one page at a time, so reads and writes to the
+/// collection keep running in between pages.
+pub struct Compaction {
+    /// Ids of the pages waiting to be compacted.
+    pending: VecDeque<u32>,
+    progress: CompactionProgress,
+}
+
+impl Compaction {
+    pub fn new() -> Self {
+        Compaction {
+            pending: VecDeque::new(),
+            progress: CompactionProgress::default(),
+        }
+    }
+
+    /// Queues every page for compaction, resetting the progress. Returns false if a compaction is
+    /// already running.
+    pub fn start(&mut self, page_ids: Vec<u32>) -> bool {
+        if self.progress.running {
+            return false;
+        }
+
+        self.progress = CompactionProgress {
+            running: !page_ids.is_empty(),
+            pages_total: page_ids.len(),
+            ..Default::default()
+        };
+        self.pending = page_ids.into_iter().collect();
+
+        true
+    }
+
+    /// The id of the next page to compact.
+    pub fn next_page(&self) -> Option<u32> {
+        self.pending.front().cloned()
+    }
+
+    /// Records the compaction of the next page.
+    pub fn record(&mut self, page: PageCompaction) {
+        self.pending.pop_front();
+
+        self.progress.pages_compacted += 1;
+        self.progress.bytes_reclaimed += page.bytes_reclaimed;
+        self.progress.documents_moved += page.documents_moved;
+        self.progress.pages_released += page.pages_released;
+        self.progress.running = !self.pending.is_empty();
+    }
+
+    /// Stops the compaction, leaving the remaining pages as they are.
+    pub fn abort(&mut self) {
+        self.pending.clear();
+        self.progress.running = false;
+    }
+
+    pub fn progress(&self) -> &CompactionProgress {
+        &self.progress
+    }
+}
+
+/// Starts the background thread that runs the compaction job of every collection of every
+/// database, compacting a single page per collection each step. A collection is only locked for
+/// writing while one of its pages is compacted.
+pub fn spawn_worker(registry: Arc<DatabaseRegistry>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(COMPACTION_STEP_INTERVAL);
+
+        for db in registry.databases() {
+            for collection in db.collections() {
+                if !collection.read().unwrap().compaction().running {
+                    continue;
+                }
+
+                // Failures are logged and stop the job of the collection.
+                let name = {
+                    let mut collection = collection.write().unwrap();
+                    let _ = collection.compact_next();
+                    collection.name().original().clone()
+                };
+
+                if let Err(e) = db.update_catalog(&name) {
+                    s_log(Error, Filesystem, &*format!("[Catalog-Update] {}", e));
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress() {
+        let mut compaction = Compaction::new();
+
+        assert!(compaction.start(vec![0, 1]));
+        assert!(!compaction.start(vec![0, 1]));
+        assert_eq!(compaction.next_page(), Some(0));
+
+        compaction.record(PageCompaction {
+            bytes_reclaimed: 64,
+            documents_moved: 1,
+            pages_released: 0,
+        });
+        compaction.record(PageCompaction {
+            bytes_reclaimed: 32,
+            documents_moved: 0,
+            pages_released: 1,
+        });
+
+        let progress = compaction.progress();
+        assert!(!progress.running);
+        assert_eq!(progress.pages_compacted, 2);
+        assert_eq!(progress.bytes_reclaimed, 96);
+        assert_eq!(progress.pages_released, 1);
+        assert_eq!(compaction.next_page(), None);
+    }
+}
@@ -1,19 +1,660 @@
-use crate::storage::collection::Collection;
+use crate::io::filesystem::{Filesystem, StdFs};
+use crate::io::path::DatabasePath;
+use crate::page::buffer_pool::BufferPool;
+use crate::page::error::{ReadError, WriteError};
+use crate::page::wal::{Durability, FileImage, WriteAheadLog};
+use crate::storage::catalog::{Catalog, CatalogEntry, CatalogError};
+use crate::storage::collection::{Collection, CollectionStats};
+use crate::storage::options::CollectionOptions;
+use crate::storage::utils::{CollectionNameError, CollectionNameFormatter};
+use crate::storage::view::{ViewDefinition, ViewEntry};
+use serde::Serialize;
 use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
 
-/// An in memory representation of the database.
+#[derive(Debug)]
+/// Error that occurs when changing the collections of a database.
+pub enum DatabaseError {
+    /// The collection name is not allowed.
+    InvalidName(CollectionNameError),
+    /// The collection options cannot be used together.
+    InvalidOptions(String),
+    /// A collection with the name already exists.
+    AlreadyExists(String),
+    /// No collection with the name exists.
+    NotFound(String),
+    /// No view with the name exists.
+    ViewNotFound(String),
+    /// Io error.
+    Io(io::Error),
+    /// The collection pages could not be read.
+    Read(ReadError),
+    /// The collection pages could not be written.
+    Write(WriteError),
+}
+
+impl From<io::Error> for DatabaseError {
+    fn from(e: io::Error) -> Self {
+        DatabaseError::Io(e)
+    }
+}
+
+impl From<ReadError> for DatabaseError {
+    fn from(e: ReadError) -> Self {
+        DatabaseError::Read(e)
+    }
+}
+
+impl From<WriteError> for DatabaseError {
+    fn from(e: WriteError) -> Self {
+        DatabaseError::Write(e)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A collection with basic statistics.
+pub struct CollectionSummary {
+    pub name: String,
+    pub documents: u64,
+    pub pages: usize,
+    /// When the collection was created, in seconds since the unix epoch.
+    pub created: i64,
+}
+
+/// An in memory representation of a database: a named set of collections stored in its own
+/// directory of the data directory.
+///
+/// Every collection has its own reader/writer lock, so requests to different collections never
+/// wait on each other and any amount of reads to the same collection run at once.
+///
+/// Locks are always taken in the order: collection map, collection, catalog.
 pub struct Database {
-    collections: HashMap<String, u32>,
+    name: String,
+    collections: RwLock<HashMap<String, Arc<RwLock<Collection>>>>,
+    /// Memory budget shared by the pages of every collection.
+    pool: Arc<BufferPool>,
+    /// Log every page write goes through.
+    wal: Arc<WriteAheadLog>,
+    /// The on-disk list of collections. Held while the catalog is written, so catalog writes
+    /// never overlap.
+    catalog: Mutex<Catalog>,
 }
 
 impl Database {
-    pub fn new() -> Self {
-        Database {
-            collections: HashMap::new(),
+    /// Creates a database without any collections, creating its directory if needed.
+    pub fn new(name: &str, pool: Arc<BufferPool>, wal: Arc<WriteAheadLog>) -> io::Result<Self> {
+        StdFs.create_dir(DatabasePath::Data.file(name))?;
+
+        Ok(Database {
+            name: name.to_string(),
+            collections: RwLock::new(HashMap::new()),
+            pool,
+            wal,
+            catalog: Mutex::new(Catalog::new(name.to_string())),
+        })
+    }
+
+    /// Opens a database from its directory, opening every collection recorded in its catalog.
+    pub fn open(
+        name: &str,
+        pool: Arc<BufferPool>,
+        wal: Arc<WriteAheadLog>,
+    ) -> Result<Self, CatalogError> {
+        let db = Database::new(name, pool, wal)?;
+        let catalog = Catalog::load(name.to_string())?;
+
+        {
+            let mut collections = db.collections.write().unwrap();
+
+            for entry in catalog.entries().values() {
+                let collection = Collection::open(
+                    CollectionNameFormatter::new(&entry.name).in_database(name),
+                    db.pool.clone(),
+                    db.wal.clone(),
+                    entry.options.clone(),
+                )
+                .map_err(|error| CatalogError::Collection {
+                    name: entry.name.clone(),
+                    error,
+                })?;
+
+                collections.insert(entry.name.clone(), Arc::new(RwLock::new(collection)));
+            }
+        }
+
+        *db.catalog.lock().unwrap() = catalog;
+
+        Ok(db)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get a collection by name.
+    pub fn collection(&self, name: &str) -> Option<Arc<RwLock<Collection>>> {
+        self.collections.read().unwrap().get(name).cloned()
+    }
+
+    /// Every collection of the database.
+    pub fn collections(&self) -> Vec<Arc<RwLock<Collection>>> {
+        self.collections.read().unwrap().values().cloned().collect()
+    }
+
+    /// Adds a collection to the database and records it in the catalog, replacing any
+    /// collection with the same name.
+    pub fn add_collection(&self, collection: Collection) -> io::Result<Arc<RwLock<Collection>>> {
+        let name = collection.name().original().to_string();
+
+        let mut entry = CatalogEntry::new(name.clone(), collection.options().clone());
+        entry.pages = collection.pages().len();
+
+        let mut collections = self.collections.write().unwrap();
+        {
+            let mut catalog = self.catalog.lock().unwrap();
+            let previous = catalog.remove(&name);
+            catalog.put(entry);
+            if let Err(e) = catalog.save(&self.wal) {
+                catalog.remove(&name);
+                if let Some(previous) = previous {
+                    catalog.put(previous);
+                }
+                return Err(e);
+            }
+        }
+
+        let collection = Arc::new(RwLock::new(collection));
+        collections.insert(name, collection.clone());
+
+        Ok(collection)
+    }
+
+    /// Creates an empty collection.
+    pub fn create_collection(
+        &self,
+        name: &str,
+        options: CollectionOptions,
+    ) -> Result<Arc<RwLock<Collection>>, DatabaseError> {
+        let name = CollectionNameFormatter::validate(name)
+            .map_err(DatabaseError::InvalidName)?
+            .in_database(&self.name);
+
+        options.validate().map_err(DatabaseError::InvalidOptions)?;
+        if options.capped.is_some() && options.ttl.is_some() {
+            return Err(DatabaseError::InvalidOptions(
+                "documents of a capped collection cannot expire".to_string(),
+            ));
         }
+        if options.capped.is_some() && options.history.is_some() {
+            return Err(DatabaseError::InvalidOptions(
+                "a capped collection cannot keep a history".to_string(),
+            ));
+        }
+
+        let mut collections = self.collections.write().unwrap();
+        if collections.contains_key(name.original()) {
+            return Err(DatabaseError::AlreadyExists(name.into_original()));
+        }
+
+        {
+            let mut catalog = self.catalog.lock().unwrap();
+            if catalog.view(name.original()).is_some() {
+                return Err(DatabaseError::AlreadyExists(name.into_original()));
+            }
+            catalog.put(CatalogEntry::new(name.original().clone(), options.clone()));
+            if let Err(e) = catalog.save(&self.wal) {
+                catalog.remove(name.original());
+                return Err(e.into());
+            }
+        }
+
+        let key = name.original().clone();
+        let collection = Collection::new(name, self.pool.clone(), self.wal.clone(), options);
+        let collection = Arc::new(RwLock::new(collection));
+        collections.insert(key, collection.clone());
+
+        Ok(collection)
+    }
+
+    /// Removes a collection along with its page files. The files and the catalog record are
+    /// removed together in a single write-ahead log record.
+    pub fn drop_collection(&self, name: &str) -> Result<(), DatabaseError> {
+        let mut collections = self.collections.write().unwrap();
+        let collection = match collections.get(name) {
+            Some(collection) => collection.clone(),
+            None => return Err(DatabaseError::NotFound(name.to_string())),
+        };
+
+        let mut collection = collection.write().unwrap();
+        let mut catalog = self.catalog.lock().unwrap();
+        let entry = catalog.remove(name);
+
+        // The collection is closed first so no write changes its files while they are listed.
+        let removed = collection
+            .close()
+            .map_err(DatabaseError::from)
+            .and_then(|_| {
+                let mut images: Vec<FileImage> = collection
+                    .files()?
+                    .into_iter()
+                    .map(FileImage::removed)
+                    .collect();
+                images.push(catalog.image());
+
+                Ok(self.wal.write(images, Durability::Always)?)
+            });
+
+        if let Err(e) = removed {
+            collection.reopen();
+            if let Some(entry) = entry {
+                catalog.put(entry);
+            }
+            return Err(e);
+        }
+
+        collections.remove(name);
+
+        Ok(())
+    }
+
+    /// Renames a collection along with its page files. The files are moved and the catalog record
+    /// changed together in a single write-ahead log record, without copying the files.
+    pub fn rename_collection(
+        &self,
+        name: &str,
+        new_name: &str,
+    ) -> Result<Arc<RwLock<Collection>>, DatabaseError> {
+        let new_name = CollectionNameFormatter::validate(new_name)
+            .map_err(DatabaseError::InvalidName)?
+            .in_database(&self.name);
+
+        let mut collections = self.collections.write().unwrap();
+        if collections.contains_key(new_name.original()) || self.view(new_name.original()).is_some()
+        {
+            return Err(DatabaseError::AlreadyExists(new_name.into_original()));
+        }
+        let collection = match collections.get(name) {
+            Some(collection) => collection.clone(),
+            None => return Err(DatabaseError::NotFound(name.to_string())),
+        };
+
+        let mut collection = collection.write().unwrap();
+        let mut catalog = self.catalog.lock().unwrap();
+        let mut entry = match catalog.remove(name) {
+            Some(entry) => entry,
+            None => CatalogEntry::new(name.to_string(), collection.options().clone()),
+        };
+        let old_entry = entry.clone();
+        entry.name = new_name.original().clone();
+        catalog.put(entry);
+
+        // The collection is closed first so no write changes its files while they are moved.
+        let moved = collection
+            .close()
+            .map_err(DatabaseError::from)
+            .and_then(|_| {
+                let mut images: Vec<FileImage> = collection
+                    .files()?
+                    .into_iter()
+                    .map(|file| {
+                        let renamed = collection.name().rename_file(&file, &new_name);
+                        FileImage::renamed(file, renamed)
+                    })
+                    .collect();
+                images.push(catalog.image());
+
+                Ok(self.wal.write(images, Durability::Always)?)
+            });
+
+        if let Err(e) = moved {
+            collection.reopen();
+            catalog.remove(new_name.original());
+            catalog.put(old_entry);
+            return Err(e);
+        }
+
+        // The files were moved, so the collection is only reachable by its new name from now on.
+        collections.remove(name);
+
+        let renamed = Collection::open(
+            new_name.clone(),
+            self.pool.clone(),
+            self.wal.clone(),
+            collection.options().clone(),
+        )?;
+        let renamed = Arc::new(RwLock::new(renamed));
+
+        collections.insert(new_name.into_original(), renamed.clone());
+
+        Ok(renamed)
     }
 
-    pub fn collections(&mut self) -> &mut HashMap<String, u32> {
-        &mut self.collections
+    /// Every collection with basic statistics, ordered by name.
+    pub fn list_collections(&self) -> Vec<CollectionSummary> {
+        let created: HashMap<String, i64> = self
+            .catalog()
+            .into_iter()
+            .map(|entry| (entry.name, entry.created))
+            .collect();
+
+        let mut summaries: Vec<CollectionSummary> = self
+            .collections()
+            .iter()
+            .map(|collection| {
+                let collection = collection.read().unwrap();
+                let name = collection.name().original().clone();
+
+                CollectionSummary {
+                    documents: collection.pages().document_count(),
+                    pages: collection.pages().len(),
+                    created: created.get(&name).cloned().unwrap_or(0),
+                    name,
+                }
+            })
+            .collect();
+
+        summaries.sort_by(|a, b| a.name.cmp(&b.name));
+
+        summaries
+    }
+
+    /// Saves a query over a collection of the database as a view. Views share their names with
+    /// collections. A view whose source collection is dropped or renamed can no longer be read.
+    pub fn create_view(&self, name: &str, definition: ViewDefinition) -> Result<(), DatabaseError> {
+        let name = CollectionNameFormatter::validate(name)
+            .map_err(DatabaseError::InvalidName)?
+            .into_original();
+
+        let collections = self.collections.read().unwrap();
+        if collections.contains_key(&name) {
+            return Err(DatabaseError::AlreadyExists(name));
+        }
+        if !collections.contains_key(&definition.source) {
+            return Err(DatabaseError::NotFound(definition.source));
+        }
+
+        let mut catalog = self.catalog.lock().unwrap();
+        if catalog.view(&name).is_some() {
+            return Err(DatabaseError::AlreadyExists(name));
+        }
+
+        catalog.put_view(ViewEntry::new(name.clone(), definition));
+        if let Err(e) = catalog.save(&self.wal) {
+            catalog.remove_view(&name);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Removes a view. The source collection is left untouched.
+    pub fn drop_view(&self, name: &str) -> Result<(), DatabaseError> {
+        let mut catalog = self.catalog.lock().unwrap();
+
+        let view = match catalog.remove_view(name) {
+            Some(view) => view,
+            None => return Err(DatabaseError::ViewNotFound(name.to_string())),
+        };
+        if let Err(e) = catalog.save(&self.wal) {
+            catalog.put_view(view);
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    /// Get a view by name.
+    pub fn view(&self, name: &str) -> Option<ViewEntry> {
+        self.catalog.lock().unwrap().view(name).cloned()
+    }
+
+    /// Every view of the database, ordered by name.
+    pub fn views(&self) -> Vec<ViewEntry> {
+        let catalog = self.catalog.lock().unwrap();
+
+        catalog.views().values().cloned().collect()
+    }
+
+    /// Storage statistics of every collection, ordered by name.
+    pub fn collection_stats(&self) -> io::Result<Vec<CollectionStats>> {
+        let mut stats = self
+            .collections()
+            .iter()
+            .map(|collection| collection.read().unwrap().stats())
+            .collect::<io::Result<Vec<CollectionStats>>>()?;
+
+        stats.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(stats)
+    }
+
+    /// The catalog record of every collection.
+    pub fn catalog(&self) -> Vec<CatalogEntry> {
+        let catalog = self.catalog.lock().unwrap();
+
+        catalog.entries().values().cloned().collect()
+    }
+
+    /// Records the current page count of a collection in the catalog, writing the catalog only
+    /// if the count changed.
+    pub fn update_catalog(&self, name: &str) -> io::Result<()> {
+        let pages = match self.collection(name) {
+            Some(collection) => collection.read().unwrap().pages().len(),
+            None => return Ok(()),
+        };
+
+        let mut catalog = self.catalog.lock().unwrap();
+        match catalog.get_mut(name) {
+            Some(entry) if entry.pages != pages => entry.pages = pages,
+            _ => return Ok(()),
+        }
+
+        catalog.save(&self.wal)
+    }
+
+    pub fn pool(&self) -> &Arc<BufferPool> {
+        &self.pool
+    }
+
+    pub fn wal(&self) -> &Arc<WriteAheadLog> {
+        &self.wal
+    }
+
+    /// Closes every collection, returning the file images that remove the files of the
+    /// database. The database must not be used afterwards, unless the images could not be
+    /// written and it is reopened with [`Database::reopen_all`]. If a collection cannot be
+    /// closed, every collection is reopened.
+    pub fn close_all(&self) -> Result<Vec<FileImage>, DatabaseError> {
+        let mut images = Vec::new();
+        for collection in self.collections() {
+            let mut collection = collection.write().unwrap();
+
+            let files = collection
+                .close()
+                .map_err(DatabaseError::from)
+                .and_then(|_| Ok(collection.files()?));
+            match files {
+                Ok(files) => images.extend(files.into_iter().map(FileImage::removed)),
+                Err(e) => {
+                    drop(collection);
+                    self.reopen_all();
+                    return Err(e);
+                }
+            }
+        }
+
+        images.push(self.catalog.lock().unwrap().removal());
+
+        Ok(images)
+    }
+
+    /// Allows writes to every collection closed by [`Database::close_all`] again.
+    pub fn reopen_all(&self) {
+        for collection in self.collections() {
+            collection.write().unwrap().reopen();
+        }
+    }
+}
+
+/// Frees the least recently used pages of the given collections until the buffer pool is
+/// within its budget or no page can be freed.
+///
+/// Collections locked for writing are skipped, so this must not be called while holding the
+/// lock of a collection.
+pub fn balance_pool(
+    pool: &BufferPool,
+    collections: &[Arc<RwLock<Collection>>],
+) -> Result<(), WriteError> {
+    while pool.is_over_budget() {
+        let guards: Vec<_> = collections
+            .iter()
+            .filter_map(|c| c.try_read().ok())
+            .collect();
+        let lru = guards
+            .iter()
+            .filter_map(|c| c.lru_time().map(|t| (t, c)))
+            .min_by_key(|(t, _)| *t);
+
+        let released = match lru {
+            Some((_, collection)) => collection.release_lru()?,
+            None => None,
+        };
+
+        if released.is_none() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::io::file_descriptor::FileDescriptor;
+    use crate::lib::testing::with_data_dir;
+    use crate::page::wal::{DEFAULT_BATCH_INTERVAL, DEFAULT_CHECKPOINT_SIZE};
+    use crate::storage::document::Document;
+
+    fn database() -> Database {
+        Database::new(
+            "shop",
+            Arc::new(BufferPool::new(usize::MAX)),
+            Arc::new(WriteAheadLog::new(
+                DEFAULT_CHECKPOINT_SIZE,
+                DEFAULT_BATCH_INTERVAL,
+            )),
+        )
+        .unwrap()
+    }
+
+    fn file_exists(name: &str) -> bool {
+        StdFs.file_exists(&FileDescriptor {
+            path: DatabasePath::Data,
+            name: name.to_string(),
+        })
+    }
+
+    fn insert(db: &Database, name: &str, count: u64) {
+        let documents = (0..count)
+            .map(|n| Document::new(json!({ "n": n }).as_object().unwrap().clone()))
+            .collect();
+
+        let collection = db.collection(name).unwrap();
+        collection
+            .write()
+            .unwrap()
+            .insert_many(documents, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_create_collection() {
+        with_data_dir(|| {
+            let db = database();
+
+            for name in ["", "_users", "users.0", "con"].iter() {
+                let created = db.create_collection(name, CollectionOptions::default());
+                assert!(matches!(created, Err(DatabaseError::InvalidName(_))));
+            }
+            assert!(db.catalog().is_empty());
+
+            db.create_collection("users", CollectionOptions::default())
+                .unwrap();
+            let created = db.create_collection("users", CollectionOptions::default());
+            assert!(matches!(created, Err(DatabaseError::AlreadyExists(_))));
+
+            let names: Vec<String> = db.catalog().into_iter().map(|entry| entry.name).collect();
+            assert_eq!(names, vec!["users".to_string()]);
+        });
+    }
+
+    #[test]
+    fn test_rename_collection() {
+        with_data_dir(|| {
+            let db = database();
+            db.create_collection("users", CollectionOptions::default())
+                .unwrap();
+            insert(&db, "users", 3);
+            let files = db
+                .collection("users")
+                .unwrap()
+                .read()
+                .unwrap()
+                .files()
+                .unwrap();
+            assert!(!files.is_empty());
+
+            let renamed = db.rename_collection("users", "people").unwrap();
+
+            let moved = renamed.read().unwrap().files().unwrap();
+            assert_eq!(moved.len(), files.len());
+            assert!(moved.iter().all(|file| file.starts_with("shop/people.")));
+            assert!(files.iter().all(|file| !file_exists(file)));
+            assert!(db.collection("users").is_none());
+
+            let names: Vec<String> = db.catalog().into_iter().map(|entry| entry.name).collect();
+            assert_eq!(names, vec!["people".to_string()]);
+
+            // The renamed collection is found by its new name when the database is opened again.
+            let reopened = Database::open("shop", db.pool().clone(), db.wal().clone()).unwrap();
+            assert!(reopened.collection("users").is_none());
+            let people = reopened.collection("people").unwrap();
+            assert_eq!(people.read().unwrap().pages().document_count(), 3);
+
+            let renamed = db.rename_collection("users", "customers");
+            assert!(matches!(renamed, Err(DatabaseError::NotFound(_))));
+        });
+    }
+
+    #[test]
+    fn test_drop_collection() {
+        with_data_dir(|| {
+            let db = database();
+
+            let dropped = db.drop_collection("users");
+            assert!(matches!(dropped, Err(DatabaseError::NotFound(_))));
+
+            db.create_collection("users", CollectionOptions::default())
+                .unwrap();
+            insert(&db, "users", 3);
+            let files = db
+                .collection("users")
+                .unwrap()
+                .read()
+                .unwrap()
+                .files()
+                .unwrap();
+
+            db.drop_collection("users").unwrap();
+
+            assert!(files.iter().all(|file| !file_exists(file)));
+            assert!(db.collection("users").is_none());
+            assert!(db.catalog().is_empty());
+        });
     }
 }
@@ -1,5 +1,8 @@
+use crate::lib::json::bsonio::encoder::encode_json_object;
 use crate::lib::json::types::JsonObject;
+use crate::page::page::{PageReadable, PageWriteable};
 
+#[derive(Debug, Clone, PartialEq)]
 /// A JSON document, stored internally using BSON encoding.
 pub struct Document {
     inner: JsonObject,
@@ -13,6 +16,10 @@ impl Document {
     pub fn as_json(&self) -> &JsonObject {
         &self.inner
     }
+
+    pub fn as_json_mut(&mut self) -> &mut JsonObject {
+        &mut self.inner
+    }
 }
 
 impl From<JsonObject> for Document {
@@ -20,3 +27,15 @@ impl From<JsonObject> for Document {
         Document::new(o)
     }
 }
+
+impl PageReadable for Document {
+    fn read(o: JsonObject) -> Self {
+        Document::new(o)
+    }
+}
+
+impl PageWriteable for &Document {
+    fn write(self) -> Vec<u8> {
+        encode_json_object(self.inner.clone())
+    }
+}
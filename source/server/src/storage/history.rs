@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::io::logger::s_log;
+use crate::io::logger::EventCategory::Background;
+use crate::io::logger::EventSeverity::{Error, Info};
+use crate::lib::json::types::JsonObject;
+use crate::page::error::WriteError;
+use crate::page::page_set::PageSet;
+use crate::page::record_id::RecordId;
+use crate::page::wal::Durability;
+use crate::storage::document::Document;
+use crate::storage::ids::{id_key, ID_FIELD};
+use crate::storage::options::{self, HistoryOptions};
+use crate::storage::registry::DatabaseRegistry;
+use crate::storage::ttl;
+
+/// Delay between runs of the history pruner.
+pub const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+const VERSION_FIELD: &str = "version";
+const TIME_FIELD: &str = "time";
+const DOCUMENT_FIELD: &str = "document";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A version of a document.
+pub struct Version {
+    /// Increases with every version recorded in the collection.
+    pub version: u64,
+    /// When the version was written, formatted as RFC 3339.
+    pub time: String,
+    /// The document as written, or None if the version deleted the document.
+    pub document: Option<JsonObject>,
+}
+
+/// A version as stored in the history pages.
+struct StoredVersion {
+    record: RecordId,
+    id: Value,
+    version: u64,
+    time: DateTime<Utc>,
+    document: Option<JsonObject>,
+}
+
+impl StoredVersion {
+    fn decode(record: RecordId, document: Document) -> Option<Self> {
+        let mut stored = document.as_json().clone();
+
+        Some(StoredVersion {
+            record,
+            id: stored.remove(ID_FIELD)?,
+            version: stored.get(VERSION_FIELD)?.as_u64()?,
+            time: Utc
+                .timestamp_millis_opt(stored.get(TIME_FIELD)?.as_i64()?)
+                .single()?,
+            document: match stored.remove(DOCUMENT_FIELD)? {
+                Value::Object(document) => Some(document),
+                _ => None,
+            },
+        })
+    }
+}
+
+/// The outcome of a single run of the history pruner.
+#[derive(Default)]
+pub struct PrunerRun {
+    /// The amount of collections with a history that were pruned.
+    pub collections: usize,
+    /// The amount of versions deleted.
+    pub pruned: u64,
+    /// The amount of collections that could not be pruned.
+    pub failed: usize,
+}
+
+/// The earlier versions of the documents of a collection, stored in their own page set.
+///
+/// Every write to the collection appends a version for each document it changes: the document
+/// as written, or a deletion. Reading the collection as of a time takes the latest version of
+/// every document written at or before that time.
+///
+/// Versions are stored as documents with the `_id` of the document they belong to, the version
+/// number, the time of the write in milliseconds since the unix epoch and the document, which is
+/// null for deletions.
+pub struct History {
+    pages: PageSet,
+    retention: chrono::Duration,
+    /// The number given to the next recorded version, known once the history is first written to.
+    next_version: Option<u64>,
+}
+
+impl History {
+    pub fn new(pages: PageSet, options: &HistoryOptions) -> Self {
+        History {
+            pages,
+            retention: options::seconds(options.retention_seconds)
+                .unwrap_or_else(chrono::Duration::max_value),
+            next_version: None,
+        }
+    }
+
+    pub fn pages(&self) -> &PageSet {
+        &self.pages
+    }
+
+    pub fn pages_mut(&mut self) -> &mut PageSet {
+        &mut self.pages
+    }
+
+    /// The earliest time the collection can be read as of at `now`. Versions replaced before then
+    /// may already be pruned. A retention longer than any date goes back keeps every version.
+    pub fn horizon(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        now.checked_sub_signed(self.retention)
+            .unwrap_or(chrono::MIN_DATETIME)
+    }
+
+    /// Appends a version for each changed document, given by its `_id` and its new contents or
+    /// None if it was deleted.
+    pub fn record(
+        &mut self,
+        changes: Vec<(Value, Option<JsonObject>)>,
+        time: DateTime<Utc>,
+        durability: Durability,
+    ) -> Result<(), WriteError> {
+        let mut next = match self.next_version {
+            Some(next) => next,
+            None => self
+                .versions()?
+                .iter()
+                .map(|v| v.version + 1)
+                .max()
+                .unwrap_or(0),
+        };
+
+        for (id, document) in changes {
+            let stored = json!({
+                ID_FIELD: id,
+                VERSION_FIELD: next,
+                TIME_FIELD: time.timestamp_millis(),
+                DOCUMENT_FIELD: document,
+            });
+
+            self.pages.insert(
+                Document::new(stored.as_object().unwrap().clone()),
+                durability,
+            )?;
+            next += 1;
+        }
+
+        self.next_version = Some(next);
+
+        Ok(())
+    }
+
+    /// Every version of a document, oldest first.
+    pub fn versions_of(&self, id: &Value) -> Result<Vec<Version>, WriteError> {
+        let mut versions: Vec<StoredVersion> = self
+            .versions()?
+            .into_iter()
+            .filter(|v| v.id == *id)
+            .collect();
+        versions.sort_by_key(|v| v.version);
+
+        Ok(versions
+            .into_iter()
+            .map(|v| Version {
+                version: v.version,
+                time: v.time.to_rfc3339(),
+                document: v.document,
+            })
+            .collect())
+    }
+
+    /// The documents of the collection as they were at a time, in the order they were first
+    /// written.
+    pub fn documents_as_of(&self, as_of: DateTime<Utc>) -> Result<Vec<JsonObject>, WriteError> {
+        let mut versions = self.versions()?;
+        versions.sort_by_key(|v| v.version);
+
+        let mut order = Vec::new();
+        let mut latest: HashMap<String, Option<JsonObject>> = HashMap::new();
+
+        for version in versions.into_iter().filter(|v| v.time <= as_of) {
+            let key = id_key(&version.id);
+            if !latest.contains_key(&key) {
+                order.push(key.clone());
+            }
+            latest.insert(key, version.document);
+        }
+
+        Ok(order
+            .into_iter()
+            .filter_map(|key| latest.remove(&key).flatten())
+            .collect())
+    }
+
+    /// Deletes the versions no read within the retention can see anymore, returning the amount of
+    /// versions deleted.
+    ///
+    /// Of the versions written before the horizon, only the latest version of each document is
+    /// still visible from the horizon on, and not even that one if it is a deletion.
+    pub fn prune(&mut self, now: DateTime<Utc>, durability: Durability) -> Result<u64, WriteError> {
+        let horizon = self.horizon(now);
+
+        let mut expired: HashMap<String, Vec<StoredVersion>> = HashMap::new();
+        for version in self.versions()?.into_iter().filter(|v| v.time < horizon) {
+            expired
+                .entry(id_key(&version.id))
+                .or_default()
+                .push(version);
+        }
+
+        let mut pruned = 0;
+        for (_, mut versions) in expired {
+            versions.sort_by_key(|v| v.version);
+            if versions.last().unwrap().document.is_some() {
+                versions.pop();
+            }
+
+            for version in versions {
+                if self.pages.delete(version.record, durability)? {
+                    pruned += 1;
+                }
+            }
+        }
+
+        Ok(pruned)
+    }
+
+    /// Flushes and releases every history page, see [`PageSet::release_all`].
+    pub fn close(&mut self) -> Result<(), WriteError> {
+        self.pages.flush_all()?;
+        self.pages.release_all()
+    }
+
+    fn versions(&self) -> Result<Vec<StoredVersion>, WriteError> {
+        Ok(self
+            .pages
+            .documents()?
+            .into_iter()
+            .filter_map(|(record, document)| StoredVersion::decode(record, document))
+            .collect())
+    }
+}
+
+/// Parses an `asOf` time, either an RFC 3339 date or milliseconds since the unix epoch.
+pub fn parse_as_of(s: &str) -> Option<DateTime<Utc>> {
+    match s.parse::<i64>() {
+        Ok(millis) => Utc.timestamp_millis_opt(millis).single(),
+        Err(_) => ttl::parse_date(&Value::from(s)),
+    }
+}
+
+/// Deletes the versions that fell out of the retention of every collection with a history in
+/// every database. Failures are logged and skip the collection.
+pub fn prune(registry: &DatabaseRegistry) -> PrunerRun {
+    let mut run = PrunerRun::default();
+
+    for db in registry.databases() {
+        for collection in db.collections() {
+            let name = {
+                let collection = collection.read().unwrap();
+                if collection.options().history.is_none() {
+                    continue;
+                }
+                collection.name().original().clone()
+            };
+            run.collections += 1;
+
+            let pruned = collection.write().unwrap().prune_history(Utc::now());
+
+            match pruned.and_then(|pruned| registry.balance_pool().map(|_| pruned)) {
+                Ok(pruned) => run.pruned += pruned,
+                Err(e) => {
+                    run.failed += 1;
+                    s_log(
+                        Error,
+                        Background,
+                        &*format!(
+                            "[History-Prune] database={} collection={} {:?}",
+                            db.name(),
+                            name,
+                            e
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    run
+}
+
+/// Starts the background thread that prunes the history of collections on a schedule, logging
+/// the outcome of every run.
+pub fn spawn_pruner(registry: Arc<DatabaseRegistry>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(PRUNE_INTERVAL);
+
+        let start = Instant::now();
+        let run = prune(&registry);
+
+        s_log(
+            Info,
+            Background,
+            &*format!(
+                "[History-Run] collections={} pruned={} failed={} elapsed_ms={}",
+                run.collections,
+                run.pruned,
+                run.failed,
+                start.elapsed().as_millis()
+            ),
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_as_of() {
+        assert_eq!(
+            parse_as_of("1000"),
+            Some(Utc.timestamp_millis_opt(1000).unwrap())
+        );
+        assert_eq!(
+            parse_as_of("1970-01-01T00:00:01Z"),
+            Some(Utc.timestamp_millis_opt(1000).unwrap())
+        );
+        assert_eq!(parse_as_of("yesterday"), None);
+    }
+}
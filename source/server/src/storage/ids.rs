@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::mem;
+
+use serde_json::Value;
+
+use crate::lib::json::types::JsonObject;
+use crate::lib::uid::{IntUid, SortableUid, UidGenerator, UuidUid};
+use crate::page::error::WriteError;
+use crate::page::record_id::RecordId;
+use crate::storage::document::Document;
+use crate::storage::options::IdGenerator;
+
+/// Name of the field that identifies a document within its collection.
+pub const ID_FIELD: &str = "_id";
+
+/// Generates the `_id` of documents inserted without one.
+enum DocumentIdGenerator {
+    Int(IntUid),
+    Uuid(UuidUid),
+    Sortable(SortableUid),
+}
+
+impl UidGenerator for DocumentIdGenerator {
+    type Value = Value;
+
+    fn generate_uid(&mut self) -> Self::Value {
+        match self {
+            DocumentIdGenerator::Int(uid) => Value::from(uid.generate_uid()),
+            DocumentIdGenerator::Uuid(uid) => Value::from(uid.generate_uid()),
+            DocumentIdGenerator::Sortable(uid) => Value::from(uid.generate_uid()),
+        }
+    }
+}
+
+/// The `_id` of every document in a collection, for assigning ids to new documents and rejecting
+/// duplicate ids.
+pub struct IdIndex {
+    /// Record id of every document, by the JSON encoding of its `_id`.
+    ids: HashMap<String, RecordId>,
+    generator: DocumentIdGenerator,
+}
+
+impl IdIndex {
+    /// Builds the index from the documents of a collection. Documents without an `_id` are
+    /// skipped.
+    pub fn build(generator: IdGenerator, documents: &[(RecordId, Document)]) -> Self {
+        let mut ids = HashMap::new();
+        let mut largest_int = None;
+
+        for (record, document) in documents {
+            if let Some(id) = document.as_json().get(ID_FIELD) {
+                if let Some(int) = id.as_u64() {
+                    largest_int = largest_int.max(Some(int));
+                }
+                ids.insert(id_key(id), *record);
+            }
+        }
+
+        // Past the largest integer, generated ids start over at 0 and skip the ids in use.
+        let start = largest_int.map_or(Some(0), |id: u64| id.checked_add(1));
+        let generator = match generator {
+            IdGenerator::Int => {
+                DocumentIdGenerator::Int(start.map_or_else(IntUid::new, IntUid::starting_at))
+            }
+            IdGenerator::Uuid => DocumentIdGenerator::Uuid(UuidUid),
+            IdGenerator::Sortable => DocumentIdGenerator::Sortable(SortableUid::new()),
+        };
+
+        IdIndex { ids, generator }
+    }
+
+    /// Gives a document an `_id` if it has none, returning its `_id`. Fails if the `_id` of the
+    /// document is already used by another document or by one of the `pending` ids.
+    pub fn assign(
+        &mut self,
+        document: &mut JsonObject,
+        pending: &[Value],
+    ) -> Result<Value, WriteError> {
+        if let Some(id) = document.get(ID_FIELD) {
+            if id.is_null() || id.is_array() {
+                return Err(WriteError::InvalidId(id.clone()));
+            }
+            if self.contains(id) || pending.contains(id) {
+                return Err(WriteError::DuplicateId(id.clone()));
+            }
+
+            return Ok(id.clone());
+        }
+
+        // Generated ids can only collide with ids given by users.
+        let id = loop {
+            let id = self.generator.generate_uid();
+            if !self.contains(&id) && !pending.contains(&id) {
+                break id;
+            }
+        };
+        document.insert(ID_FIELD.to_string(), id.clone());
+
+        Ok(id)
+    }
+
+    pub fn contains(&self, id: &Value) -> bool {
+        self.ids.contains_key(&id_key(id))
+    }
+
+    /// Records the `_id` of a stored document.
+    pub fn insert(&mut self, id: &Value, record: RecordId) {
+        self.ids.insert(id_key(id), record);
+    }
+
+    /// Forgets the `_id` of a deleted document.
+    pub fn remove(&mut self, id: &Value) {
+        self.ids.remove(&id_key(id));
+    }
+
+    /// The approximate amount of memory used by the index in bytes.
+    pub fn size(&self) -> usize {
+        self.ids
+            .keys()
+            .map(|key| key.len() + mem::size_of::<RecordId>())
+            .sum()
+    }
+
+    /// Forgets the `_id` of every document stored in the pages.
+    pub fn remove_pages(&mut self, pages: &[u32]) {
+        self.ids.retain(|_, record| !pages.contains(&record.page));
+    }
+}
+
+/// The key of an `_id` in maps of ids. Ids are keyed by their JSON encoding, so ids of different
+/// types never collide.
+pub fn id_key(id: &Value) -> String {
+    serde_json::to_string(id).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        let documents = vec![(
+            RecordId::new(0, 0),
+            Document::new(json!({ "_id": 4 }).as_object().unwrap().clone()),
+        )];
+        let mut index = IdIndex::build(IdGenerator::Int, &documents);
+
+        let mut document = JsonObject::new();
+        assert_eq!(index.assign(&mut document, &[]).ok(), Some(json!(5)));
+        assert_eq!(document.get(ID_FIELD), Some(&json!(5)));
+
+        let mut duplicate = json!({ "_id": 4 }).as_object().unwrap().clone();
+        assert!(matches!(
+            index.assign(&mut duplicate, &[]),
+            Err(WriteError::DuplicateId(_))
+        ));
+
+        let mut pending = json!({ "_id": "a" }).as_object().unwrap().clone();
+        assert!(matches!(
+            index.assign(&mut pending, &[json!("a")]),
+            Err(WriteError::DuplicateId(_))
+        ));
+
+        index.remove_pages(&[0]);
+        assert!(!index.contains(&json!(4)));
+
+        let documents = vec![
+            (
+                RecordId::new(0, 0),
+                Document::new(json!({ "_id": u64::MAX }).as_object().unwrap().clone()),
+            ),
+            (
+                RecordId::new(0, 1),
+                Document::new(json!({ "_id": 0 }).as_object().unwrap().clone()),
+            ),
+        ];
+        let mut index = IdIndex::build(IdGenerator::Int, &documents);
+
+        let mut document = JsonObject::new();
+        assert_eq!(index.assign(&mut document, &[]).ok(), Some(json!(1)));
+    }
+}
@@ -1,4 +1,15 @@
+pub mod catalog;
 pub mod collection;
+pub mod compaction;
 pub mod database;
 pub mod document;
+pub mod history;
+pub mod ids;
+pub mod options;
+pub mod query;
+pub mod registry;
+pub mod tail;
+pub mod ttl;
+pub mod update;
 pub mod utils;
+pub mod view;
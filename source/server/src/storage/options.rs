@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+
+use crate::io::compressor::CompressionStrategy;
+use crate::lib::json::schema::JsonSchema;
+use crate::page::page::DEFAULT_PAGE_SIZE;
+use crate::page::slot::MIN_PAGE_SIZE;
+use crate::page::wal::Durability;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+/// Options that control how a collection is stored.
+pub struct CollectionOptions {
+    /// Store a checksum with every document record in addition to the page checksum, allowing
+    /// damage to be located within a page.
+    pub record_checksums: bool,
+    /// Algorithm used to compress page bodies on the filesystem. Pages are stored uncompressed
+    /// if none is set.
+    pub compression: Option<CompressionStrategy>,
+    /// The maximum size of a page body in bytes. Documents larger than a page are stored in
+    /// overflow pages.
+    pub page_size: usize,
+    /// The durability of writes to the collection. Requests may ask for a stronger level, but
+    /// never a weaker one.
+    pub durability: Durability,
+    /// The schema documents written to the collection are validated against.
+    pub schema: Option<JsonSchema>,
+    /// What happens to writes that do not match the schema.
+    pub validation: ValidationMode,
+    /// Limits of a capped collection. Documents of a capped collection are only ever appended,
+    /// and the oldest pages are dropped once a limit is exceeded.
+    pub capped: Option<CappedOptions>,
+    /// Expiry of documents by a date field. Expired documents are deleted by the TTL reaper.
+    pub ttl: Option<TtlOptions>,
+    /// How the `_id` of documents inserted without one is generated.
+    pub id_generator: IdGenerator,
+    /// Keeps the earlier versions of every document, so the collection can be read as it was at
+    /// an earlier time.
+    pub history: Option<HistoryOptions>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The generator of document ids.
+pub enum IdGenerator {
+    /// Incrementing integers, starting after the largest integer id in the collection.
+    Int,
+    /// Random UUIDv4 strings.
+    Uuid,
+    /// Strings that sort in insertion order, formatted like a ULID.
+    Sortable,
+}
+
+impl Default for IdGenerator {
+    fn default() -> Self {
+        IdGenerator::Int
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The limits of a capped collection. Whole pages are dropped at once, so a collection can hold
+/// fewer documents than its limits allow, and the newest page is never dropped.
+pub struct CappedOptions {
+    /// The largest total size of the stored documents in bytes.
+    pub max_size: Option<u64>,
+    /// The largest amount of stored documents.
+    pub max_documents: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// Expiry of the documents of a collection. A document expires once the date in its field is
+/// further in the past than `expire_after_seconds`. Documents without a date in the field never
+/// expire.
+pub struct TtlOptions {
+    /// Path to the date field, with nested fields separated by `.`.
+    pub field: String,
+    pub expire_after_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The version history of a collection. Versions are kept for at least `retention_seconds`
+/// after they were replaced, so the collection can be read as of any time within the retention.
+pub struct HistoryOptions {
+    pub retention_seconds: u64,
+}
+
+impl CappedOptions {
+    /// True if a collection with the size and document count is over the limits.
+    pub fn is_exceeded(&self, size: u64, documents: u64) -> bool {
+        self.max_size.map_or(false, |max| size > max)
+            || self.max_documents.map_or(false, |max| documents > max)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// How strictly the schema of a collection is enforced.
+pub enum ValidationMode {
+    /// Writes that do not match the schema are rejected.
+    Strict,
+    /// Writes that do not match the schema are logged and stored.
+    Warn,
+    /// Documents are not validated.
+    Off,
+}
+
+impl Default for ValidationMode {
+    fn default() -> Self {
+        ValidationMode::Strict
+    }
+}
+
+impl CollectionOptions {
+    /// Checks that the options can be used to store a collection, returning the reason if not.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.page_size < MIN_PAGE_SIZE {
+            return Err(format!("pageSize must be at least {}", MIN_PAGE_SIZE));
+        }
+        if let Some(ttl) = &self.ttl {
+            if seconds(ttl.expire_after_seconds).is_none() {
+                return Err(format!(
+                    "ttl.expireAfterSeconds must be at most {}",
+                    max_seconds()
+                ));
+            }
+        }
+        if let Some(history) = &self.history {
+            if seconds(history.retention_seconds).is_none() {
+                return Err(format!(
+                    "history.retentionSeconds must be at most {}",
+                    max_seconds()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The longest duration in seconds that can be added to a date.
+fn max_seconds() -> u64 {
+    chrono::Duration::max_value().num_seconds() as u64
+}
+
+/// A duration of whole seconds, or None if it is too long to be added to a date.
+pub fn seconds(seconds: u64) -> Option<chrono::Duration> {
+    if seconds > max_seconds() {
+        return None;
+    }
+
+    Some(chrono::Duration::seconds(seconds as i64))
+}
+
+impl Default for CollectionOptions {
+    fn default() -> Self {
+        CollectionOptions {
+            record_checksums: false,
+            compression: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            durability: Durability::default(),
+            schema: None,
+            validation: ValidationMode::default(),
+            capped: None,
+            ttl: None,
+            id_generator: IdGenerator::default(),
+            history: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(CollectionOptions::default().validate().is_ok());
+
+        for page_size in [0, 1, MIN_PAGE_SIZE - 1].iter() {
+            let options = CollectionOptions {
+                page_size: *page_size,
+                ..CollectionOptions::default()
+            };
+            assert!(options.validate().is_err());
+        }
+
+        let options = CollectionOptions {
+            page_size: MIN_PAGE_SIZE,
+            ..CollectionOptions::default()
+        };
+        assert!(options.validate().is_ok());
+
+        let ttl = |expire_after_seconds| CollectionOptions {
+            ttl: Some(TtlOptions {
+                field: "expires".to_string(),
+                expire_after_seconds,
+            }),
+            ..CollectionOptions::default()
+        };
+        assert!(ttl(max_seconds()).validate().is_ok());
+        assert!(ttl(max_seconds() + 1).validate().is_err());
+        assert!(ttl(u64::MAX).validate().is_err());
+
+        let history = |retention_seconds| CollectionOptions {
+            history: Some(HistoryOptions { retention_seconds }),
+            ..CollectionOptions::default()
+        };
+        assert!(history(max_seconds()).validate().is_ok());
+        assert!(history(u64::MAX).validate().is_err());
+    }
+}
@@ -0,0 +1,386 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lib::json::types::JsonObject;
+
+/// Looks up the value at a path into a document, with nested fields separated by `.`.
+pub fn lookup<'a>(document: &'a JsonObject, path: &str) -> Option<&'a Value> {
+    let mut parts = path.split('.');
+    let mut value = parts.next().and_then(|key| document.get(key));
+    for key in parts {
+        value = value.and_then(|v| v.get(key));
+    }
+
+    value
+}
+
+/// Every value at a path into a document. A path continues into an array at the element with
+/// the index given by the next part of the path, or else into every element of the array.
+fn resolve<'a>(document: &'a JsonObject, path: &str) -> Vec<&'a Value> {
+    let mut parts = path.split('.');
+    let mut values: Vec<&Value> = parts
+        .next()
+        .and_then(|key| document.get(key))
+        .into_iter()
+        .collect();
+
+    for key in parts {
+        values = values
+            .into_iter()
+            .flat_map(|value| match value {
+                Value::Object(o) => o.get(key).into_iter().collect(),
+                Value::Array(elements) => match key.parse::<usize>() {
+                    Ok(index) => elements.get(index).into_iter().collect(),
+                    Err(_) => elements
+                        .iter()
+                        .filter_map(|element| element.get(key))
+                        .collect(),
+                },
+                _ => Vec::new(),
+            })
+            .collect();
+    }
+
+    values
+}
+
+/// Compares two values of the same type. Values of different types are not ordered.
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+/// True if two values are equal, regardless of how numbers are represented.
+pub fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_)) => compare(a, b) == Some(Ordering::Equal),
+        _ => a == b,
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A condition on the values at a path.
+enum Operator {
+    Eq(Value),
+    Ne(Value),
+    Gt(Value),
+    Gte(Value),
+    Lt(Value),
+    Lte(Value),
+    In(Vec<Value>),
+    Nin(Vec<Value>),
+    Exists(bool),
+    Regex(Regex),
+    Not(Vec<Operator>),
+}
+
+impl Operator {
+    /// Parses the operators given for a path. A value that is not an object of operators is
+    /// matched by equality.
+    fn parse_all(path: &str, value: &Value) -> Result<Vec<Operator>, String> {
+        let operators = match value {
+            Value::Object(o) if o.keys().any(|key| key.starts_with('$')) => o,
+            value => return Ok(vec![Operator::Eq(value.clone())]),
+        };
+
+        let mut parsed = Vec::new();
+        for (name, arg) in operators.iter() {
+            let operator = match name.as_str() {
+                "$eq" => Operator::Eq(arg.clone()),
+                "$ne" => Operator::Ne(arg.clone()),
+                "$gt" => Operator::Gt(arg.clone()),
+                "$gte" => Operator::Gte(arg.clone()),
+                "$lt" => Operator::Lt(arg.clone()),
+                "$lte" => Operator::Lte(arg.clone()),
+                "$in" | "$nin" => {
+                    let values = match arg {
+                        Value::Array(values) => values.clone(),
+                        _ => return Err(format!("{}: {} must be an array", path, name)),
+                    };
+
+                    if name == "$in" {
+                        Operator::In(values)
+                    } else {
+                        Operator::Nin(values)
+                    }
+                }
+                "$exists" => match arg {
+                    Value::Bool(exists) => Operator::Exists(*exists),
+                    _ => return Err(format!("{}: $exists must be a boolean", path)),
+                },
+                "$regex" => {
+                    let pattern = match arg {
+                        Value::String(pattern) => pattern,
+                        _ => return Err(format!("{}: $regex must be a string", path)),
+                    };
+                    let options = match operators.get("$options") {
+                        Some(Value::String(options)) => options.as_str(),
+                        Some(_) => return Err(format!("{}: $options must be a string", path)),
+                        None => "",
+                    };
+
+                    Operator::Regex(
+                        regex(pattern, options).map_err(|e| format!("{}: {}", path, e))?,
+                    )
+                }
+                "$options" if operators.contains_key("$regex") => continue,
+                "$options" => return Err(format!("{}: $options requires $regex", path)),
+                "$not" => Operator::Not(Operator::parse_all(path, arg)?),
+                name if name.starts_with('$') => {
+                    return Err(format!("{}: unknown operator {}", path, name))
+                }
+                _ => {
+                    return Err(format!(
+                        "{}: an object of operators cannot have fields",
+                        path
+                    ))
+                }
+            };
+
+            parsed.push(operator);
+        }
+
+        Ok(parsed)
+    }
+
+    /// True if the values at a path satisfy the operator. Operators other than `$exists` also
+    /// test the elements of array values, so an array matches if any of its elements do.
+    fn matches(&self, values: &[&Value]) -> bool {
+        let mut candidates = values.iter().flat_map(|value| {
+            let elements = match value {
+                Value::Array(elements) => elements.as_slice(),
+                _ => &[],
+            };
+            std::iter::once(*value).chain(elements.iter())
+        });
+
+        match self {
+            // A missing field is equal to null.
+            Operator::Eq(Value::Null) if values.is_empty() => true,
+            Operator::Eq(expected) => candidates.any(|value| equals(value, expected)),
+            Operator::Ne(expected) => !Operator::Eq(expected.clone()).matches(values),
+            Operator::Gt(bound) => {
+                candidates.any(|value| compare(value, bound) == Some(Ordering::Greater))
+            }
+            Operator::Gte(bound) => candidates.any(|value| {
+                matches!(
+                    compare(value, bound),
+                    Some(Ordering::Greater | Ordering::Equal)
+                )
+            }),
+            Operator::Lt(bound) => {
+                candidates.any(|value| compare(value, bound) == Some(Ordering::Less))
+            }
+            Operator::Lte(bound) => candidates.any(|value| {
+                matches!(
+                    compare(value, bound),
+                    Some(Ordering::Less | Ordering::Equal)
+                )
+            }),
+            Operator::In(expected) => expected
+                .iter()
+                .any(|expected| Operator::Eq(expected.clone()).matches(values)),
+            Operator::Nin(expected) => !Operator::In(expected.clone()).matches(values),
+            Operator::Exists(exists) => values.is_empty() != *exists,
+            Operator::Regex(regex) => {
+                candidates.any(|value| value.as_str().map_or(false, |s| regex.is_match(s)))
+            }
+            Operator::Not(operators) => !operators.iter().all(|operator| operator.matches(values)),
+        }
+    }
+}
+
+/// Builds a regular expression with the flags of `$options`: `i` ignores case, `m` matches `^`
+/// and `$` at line breaks, `s` lets `.` match line breaks and `x` ignores whitespace.
+fn regex(pattern: &str, options: &str) -> Result<Regex, String> {
+    let mut builder = RegexBuilder::new(pattern);
+    for option in options.chars() {
+        match option {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            _ => return Err(format!("unknown $regex option {}", option)),
+        };
+    }
+
+    builder.build().map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone)]
+/// A single entry of a filter.
+enum Condition {
+    /// The values at a path satisfy every operator.
+    Field(String, Vec<Operator>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Condition {
+    fn matches(&self, document: &JsonObject) -> bool {
+        match self {
+            Condition::Field(path, operators) => {
+                let values = resolve(document, path);
+                operators.iter().all(|operator| operator.matches(&values))
+            }
+            Condition::And(filters) => filters.iter().all(|filter| filter.matches(document)),
+            Condition::Or(filters) => filters.iter().any(|filter| filter.matches(document)),
+            Condition::Not(filter) => !filter.matches(document),
+        }
+    }
+}
+
+/// Parses the filters of `$and` and `$or`.
+fn parse_filters(name: &str, value: &Value) -> Result<Vec<Filter>, String> {
+    match value {
+        Value::Array(filters) if !filters.is_empty() => filters
+            .iter()
+            .map(|filter| match filter {
+                Value::Object(o) => Filter::try_from(o.clone()),
+                _ => Err(format!("{} must only contain filters", name)),
+            })
+            .collect(),
+        _ => Err(format!("{} must be a non-empty array of filters", name)),
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(try_from = "JsonObject", into = "JsonObject")]
+/// A filter that selects documents by the values of their fields.
+///
+/// Each key of the filter is a path to a field, with nested fields separated by `.`, mapped to
+/// the value the field must be equal to, or to an object of operators: `$eq`, `$ne`, `$gt`,
+/// `$gte`, `$lt`, `$lte`, `$in`, `$nin`, `$exists`, `$regex` with `$options`, and `$not` with
+/// operators to negate. A path into an array matches if any element of the array matches.
+///
+/// `$and` and `$or` take a list of filters, and `$not` takes a single filter. A document matches
+/// if it matches every entry of the filter.
+pub struct Filter {
+    spec: JsonObject,
+    conditions: Vec<Condition>,
+}
+
+impl TryFrom<JsonObject> for Filter {
+    type Error = String;
+
+    fn try_from(spec: JsonObject) -> Result<Self, Self::Error> {
+        let mut conditions = Vec::new();
+
+        for (key, value) in spec.iter() {
+            let condition = match key.as_str() {
+                "$and" => Condition::And(parse_filters(key, value)?),
+                "$or" => Condition::Or(parse_filters(key, value)?),
+                "$not" => match value {
+                    Value::Object(o) => Condition::Not(Box::new(Filter::try_from(o.clone())?)),
+                    _ => return Err("$not must be a filter".to_string()),
+                },
+                key if key.starts_with('$') => return Err(format!("unknown operator {}", key)),
+                path => Condition::Field(path.to_string(), Operator::parse_all(path, value)?),
+            };
+
+            conditions.push(condition);
+        }
+
+        Ok(Filter { spec, conditions })
+    }
+}
+
+impl From<Filter> for JsonObject {
+    fn from(filter: Filter) -> Self {
+        filter.spec
+    }
+}
+
+impl Filter {
+    /// True if the document matches every condition of the filter. An empty filter matches
+    /// every document.
+    pub fn matches(&self, document: &JsonObject) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(document))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::lib::testing::object;
+
+    fn filter(value: Value) -> Filter {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_matches() {
+        let document = object(json!({ "name": "Ann", "address": { "city": "Oslo" } }));
+
+        assert!(filter(json!({ "address.city": "Oslo" })).matches(&document));
+        assert!(!filter(json!({ "name": "Ann", "address.city": "Bergen" })).matches(&document));
+
+        assert!(Filter::default().matches(&document));
+    }
+
+    #[test]
+    fn test_operators() {
+        let document = object(json!({ "name": "Ann", "age": 31, "nick": null }));
+
+        assert!(filter(json!({ "age": { "$gte": 31.0, "$lt": 40 } })).matches(&document));
+        assert!(!filter(json!({ "age": { "$gt": "30" } })).matches(&document));
+        assert!(filter(json!({ "name": { "$in": ["Bob", "Ann"] } })).matches(&document));
+        assert!(
+            filter(json!({ "name": { "$nin": ["Bob"] }, "age": { "$ne": 30 } })).matches(&document)
+        );
+        assert!(
+            filter(json!({ "email": { "$exists": false }, "nick": { "$exists": true } }))
+                .matches(&document)
+        );
+        assert!(filter(json!({ "email": null })).matches(&document));
+        assert!(filter(json!({ "name": { "$regex": "^a", "$options": "i" } })).matches(&document));
+        assert!(filter(json!({ "age": { "$not": { "$gt": 40 } } })).matches(&document));
+        assert!(filter(json!({
+            "$or": [{ "name": "Bob" }, { "age": { "$lte": 31 } }],
+            "$not": { "name": "Kim" }
+        }))
+        .matches(&document));
+        assert!(!filter(json!({ "$and": [{ "name": "Ann" }, { "age": 30 }] })).matches(&document));
+
+        let invalid = [
+            json!({ "age": { "$near": 1 } }),
+            json!({ "age": { "$in": 1 } }),
+            json!({ "age": { "$gt": 1, "max": 2 } }),
+            json!({ "$or": [] }),
+            json!({ "name": { "$regex": "(" } }),
+        ];
+        for spec in invalid.iter() {
+            assert!(serde_json::from_value::<Filter>(spec.clone()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_array_paths() {
+        let document = object(json!({
+            "tags": ["red", "blue"],
+            "orders": [{ "total": 10, "items": [{ "sku": "a" }] }, { "total": 25 }]
+        }));
+
+        assert!(filter(json!({ "tags": "blue" })).matches(&document));
+        assert!(filter(json!({ "tags": ["red", "blue"] })).matches(&document));
+        assert!(filter(json!({ "tags.1": "blue" })).matches(&document));
+        assert!(filter(json!({ "orders.total": { "$gt": 20 } })).matches(&document));
+        assert!(!filter(json!({ "orders.0.total": { "$gt": 20 } })).matches(&document));
+        assert!(filter(json!({ "orders.items.sku": "a" })).matches(&document));
+        assert!(filter(json!({ "orders.items.sku": { "$exists": true } })).matches(&document));
+        assert!(!filter(json!({ "tags": { "$nin": ["red"] } })).matches(&document));
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::io::file_descriptor::FileDescriptor;
+use crate::io::filesystem::{Filesystem, StdFs};
+use crate::io::path::DatabasePath;
+use crate::page::buffer_pool::BufferPool;
+use crate::page::error::WriteError;
+use crate::page::wal::{Durability, FileImage, WriteAheadLog};
+use crate::storage::catalog::CatalogError;
+use crate::storage::database::{self, Database, DatabaseError};
+use crate::storage::utils::{CollectionNameError, CollectionNameFormatter};
+
+/// Name of the database used by requests that do not choose one.
+pub const DEFAULT_DATABASE: &str = "default";
+/// Name of the registry file in the data directory.
+pub const REGISTRY_FILE_NAME: &str = "iris.databases";
+/// The registry format version written by this build.
+pub const REGISTRY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The registry record of a single database.
+pub struct DatabaseEntry {
+    pub name: String,
+    /// When the database was created, in seconds since the unix epoch.
+    pub created: i64,
+}
+
+impl DatabaseEntry {
+    /// A record for a database created now.
+    pub fn new(name: String) -> Self {
+        DatabaseEntry {
+            name,
+            created: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct RegistryFile {
+    version: u32,
+    databases: Vec<DatabaseEntry>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// A database with basic statistics.
+pub struct DatabaseSummary {
+    pub name: String,
+    pub collections: usize,
+    /// When the database was created, in seconds since the unix epoch.
+    pub created: i64,
+}
+
+#[derive(Debug)]
+/// Error that occurs when changing the databases of the server.
+pub enum RegistryError {
+    /// The database name is not allowed.
+    InvalidName(CollectionNameError),
+    /// A database with the name already exists.
+    AlreadyExists(String),
+    /// No database with the name exists.
+    NotFound(String),
+    /// The default database cannot be dropped.
+    DefaultDatabase,
+    /// Io error.
+    Io(io::Error),
+    /// The collections of the database could not be closed.
+    Database(DatabaseError),
+}
+
+impl From<io::Error> for RegistryError {
+    fn from(e: io::Error) -> Self {
+        RegistryError::Io(e)
+    }
+}
+
+impl From<DatabaseError> for RegistryError {
+    fn from(e: DatabaseError) -> Self {
+        RegistryError::Database(e)
+    }
+}
+
+struct RegisteredDatabase {
+    entry: DatabaseEntry,
+    database: Arc<Database>,
+}
+
+/// Every logical database served by the server. Each database stores its collections and its
+/// catalog in its own directory of the data directory, while the buffer pool and the
+/// write-ahead log are shared by all of them.
+///
+/// The list of databases is stored as a JSON file in the data directory, rewritten as a whole
+/// through the write-ahead log whenever it changes.
+pub struct DatabaseRegistry {
+    databases: RwLock<BTreeMap<String, RegisteredDatabase>>,
+    pool: Arc<BufferPool>,
+    wal: Arc<WriteAheadLog>,
+}
+
+impl DatabaseRegistry {
+    /// Opens every database recorded in the registry file, creating the default database if it
+    /// does not exist yet.
+    pub fn open(buffer_pool_size: usize, wal: WriteAheadLog) -> Result<Self, CatalogError> {
+        let registry = DatabaseRegistry {
+            databases: RwLock::new(BTreeMap::new()),
+            pool: Arc::new(BufferPool::new(buffer_pool_size)),
+            wal: Arc::new(wal),
+        };
+
+        let file = registry_file();
+        let mut entries = if StdFs.file_exists(&file) {
+            decode(&StdFs.read(&file)?)?
+        } else {
+            Vec::new()
+        };
+
+        if !entries.iter().any(|entry| entry.name == DEFAULT_DATABASE) {
+            entries.push(DatabaseEntry::new(DEFAULT_DATABASE.to_string()));
+            registry
+                .wal
+                .write(vec![image(&entries)], Durability::Always)?;
+        }
+
+        {
+            let mut databases = registry.databases.write().unwrap();
+
+            for entry in entries {
+                let database =
+                    Database::open(&entry.name, registry.pool.clone(), registry.wal.clone())?;
+
+                databases.insert(
+                    entry.name.clone(),
+                    RegisteredDatabase {
+                        entry,
+                        database: Arc::new(database),
+                    },
+                );
+            }
+        }
+
+        Ok(registry)
+    }
+
+    /// Get a database by name.
+    pub fn get(&self, name: &str) -> Option<Arc<Database>> {
+        let databases = self.databases.read().unwrap();
+
+        databases.get(name).map(|db| db.database.clone())
+    }
+
+    /// Every database of the server.
+    pub fn databases(&self) -> Vec<Arc<Database>> {
+        let databases = self.databases.read().unwrap();
+
+        databases.values().map(|db| db.database.clone()).collect()
+    }
+
+    /// Creates a database without any collections.
+    pub fn create_database(&self, name: &str) -> Result<Arc<Database>, RegistryError> {
+        let name = CollectionNameFormatter::validate(name)
+            .map_err(RegistryError::InvalidName)?
+            .into_original();
+
+        let mut databases = self.databases.write().unwrap();
+        if databases.contains_key(&name) {
+            return Err(RegistryError::AlreadyExists(name));
+        }
+
+        let database = Arc::new(Database::new(&name, self.pool.clone(), self.wal.clone())?);
+
+        let entry = DatabaseEntry::new(name.clone());
+        let mut entries = entries(&databases);
+        entries.push(entry.clone());
+        self.wal.write(vec![image(&entries)], Durability::Always)?;
+
+        databases.insert(
+            name,
+            RegisteredDatabase {
+                entry,
+                database: database.clone(),
+            },
+        );
+
+        Ok(database)
+    }
+
+    /// Removes a database along with every collection in it. The files of the collections, the
+    /// catalog and the registry record are removed together in a single write-ahead log record.
+    pub fn drop_database(&self, name: &str) -> Result<(), RegistryError> {
+        if name == DEFAULT_DATABASE {
+            return Err(RegistryError::DefaultDatabase);
+        }
+
+        let mut databases = self.databases.write().unwrap();
+        let database = match databases.get(name) {
+            Some(db) => db.database.clone(),
+            None => return Err(RegistryError::NotFound(name.to_string())),
+        };
+
+        let mut images = database.close_all()?;
+
+        let entries: Vec<DatabaseEntry> = entries(&databases)
+            .into_iter()
+            .filter(|entry| entry.name != name)
+            .collect();
+        images.push(image(&entries));
+
+        if let Err(e) = self.wal.write(images, Durability::Always) {
+            database.reopen_all();
+            return Err(e.into());
+        }
+        databases.remove(name);
+
+        StdFs.remove_dir(&FileDescriptor {
+            path: DatabasePath::Data,
+            name: name.to_string(),
+        })?;
+
+        Ok(())
+    }
+
+    /// Every database with basic statistics, ordered by name.
+    pub fn list(&self) -> Vec<DatabaseSummary> {
+        let databases = self.databases.read().unwrap();
+
+        databases
+            .values()
+            .map(|db| DatabaseSummary {
+                name: db.entry.name.clone(),
+                collections: db.database.collections().len(),
+                created: db.entry.created,
+            })
+            .collect()
+    }
+
+    pub fn wal(&self) -> &Arc<WriteAheadLog> {
+        &self.wal
+    }
+
+    /// Frees the least recently used pages across the collections of every database until the
+    /// buffer pool is within its budget or no page can be freed.
+    ///
+    /// Collections locked for writing are skipped, so this must not be called while holding the
+    /// lock of a collection.
+    pub fn balance_pool(&self) -> Result<(), WriteError> {
+        let collections: Vec<_> = self
+            .databases()
+            .iter()
+            .flat_map(|db| db.collections())
+            .collect();
+
+        database::balance_pool(&self.pool, &collections)
+    }
+}
+
+fn entries(databases: &BTreeMap<String, RegisteredDatabase>) -> Vec<DatabaseEntry> {
+    databases.values().map(|db| db.entry.clone()).collect()
+}
+
+/// A file image of the registry with the given databases.
+fn image(entries: &[DatabaseEntry]) -> FileImage {
+    let file = RegistryFile {
+        version: REGISTRY_VERSION,
+        databases: entries.to_vec(),
+    };
+
+    FileImage::new(
+        REGISTRY_FILE_NAME.to_string(),
+        serde_json::to_vec_pretty(&file).unwrap(),
+    )
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<DatabaseEntry>, CatalogError> {
+    let file: RegistryFile = serde_json::from_slice(bytes)?;
+
+    if file.version > REGISTRY_VERSION {
+        return Err(CatalogError::UnsupportedVersion(file.version));
+    }
+
+    Ok(file.databases)
+}
+
+fn registry_file() -> FileDescriptor {
+    FileDescriptor {
+        path: DatabasePath::Data,
+        name: REGISTRY_FILE_NAME.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() -> Result<(), CatalogError> {
+        let entries = vec![
+            DatabaseEntry::new(DEFAULT_DATABASE.to_string()),
+            DatabaseEntry::new("shop".to_string()),
+        ];
+
+        let decoded = decode(&image(&entries).bytes.unwrap())?;
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].name, "shop");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::{Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::lib::json::types::JsonObject;
+use crate::page::error::WriteError;
+use crate::page::record_id::RecordId;
+use crate::storage::collection::Collection;
+use crate::storage::document::Document;
+
+/// How long a tailable read waits for new documents if the request does not say.
+pub const DEFAULT_TAIL_TIMEOUT: Duration = Duration::from_secs(5);
+/// The longest a tailable read may wait for new documents.
+pub const MAX_TAIL_TIMEOUT: Duration = Duration::from_secs(30);
+/// The amount of documents returned by a tailable read if the request does not say.
+pub const DEFAULT_TAIL_LIMIT: usize = 100;
+
+#[derive(Serialize)]
+/// A document returned by a tailable read.
+pub struct TailedDocument {
+    /// The record id of the document, formatted as `page:slot`.
+    pub id: String,
+    pub document: JsonObject,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+/// The result of a tailable read.
+pub struct TailOutput {
+    pub documents: Vec<TailedDocument>,
+    /// The record id the next read continues after: the last document returned, or the record
+    /// id the read started after if no document was returned.
+    pub last: Option<String>,
+}
+
+impl TailOutput {
+    pub fn new(documents: Vec<(RecordId, Document)>, after: Option<RecordId>) -> Self {
+        let last = documents.last().map(|(id, _)| *id).or(after);
+
+        TailOutput {
+            documents: documents
+                .into_iter()
+                .map(|(id, document)| TailedDocument {
+                    id: id.to_string(),
+                    document: document.as_json().clone(),
+                })
+                .collect(),
+            last: last.map(|id| id.to_string()),
+        }
+    }
+}
+
+/// Wakes the readers waiting for documents to be inserted into a collection.
+pub struct InsertNotifier {
+    /// The amount of inserts so far.
+    inserts: Mutex<u64>,
+    inserted: Condvar,
+}
+
+impl InsertNotifier {
+    pub fn new() -> Self {
+        InsertNotifier {
+            inserts: Mutex::new(0),
+            inserted: Condvar::new(),
+        }
+    }
+
+    /// The amount of inserts so far.
+    pub fn inserts(&self) -> u64 {
+        *self.inserts.lock().unwrap()
+    }
+
+    /// Wakes every waiting reader.
+    pub fn notify(&self) {
+        *self.inserts.lock().unwrap() += 1;
+        self.inserted.notify_all();
+    }
+
+    /// Waits until the amount of inserts is past `seen` or the timeout elapses.
+    pub fn wait(&self, seen: u64, timeout: Duration) {
+        let inserts = self.inserts.lock().unwrap();
+
+        let _ = self
+            .inserted
+            .wait_timeout_while(inserts, timeout, |inserts| *inserts <= seen)
+            .unwrap();
+    }
+}
+
+/// Reads the documents inserted into a capped collection after a record id, waiting up to the
+/// timeout for new documents if there are none yet. Returns an empty list if the timeout
+/// elapses.
+///
+/// The collection is only locked while its pages are read, never while waiting.
+pub fn tail(
+    collection: &RwLock<Collection>,
+    after: Option<RecordId>,
+    limit: usize,
+    timeout: Duration,
+) -> Result<Vec<(RecordId, Document)>, WriteError> {
+    let deadline = Instant::now() + timeout.min(MAX_TAIL_TIMEOUT);
+
+    loop {
+        let (notifier, seen) = {
+            let collection = collection.read().unwrap();
+            if collection.is_closed() {
+                return Err(WriteError::CollectionClosed(
+                    collection.name().original().clone(),
+                ));
+            }
+
+            let documents = collection.pages().documents_after(after, limit)?;
+            if !documents.is_empty() || Instant::now() >= deadline {
+                return Ok(documents);
+            }
+
+            // Inserts take the collection lock for writing, so none can happen between the read
+            // and taking the insert count.
+            let notifier = collection.insert_notifier().clone();
+            let seen = notifier.inserts();
+
+            (notifier, seen)
+        };
+
+        notifier.wait(seen, deadline.saturating_duration_since(Instant::now()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_wait_wakes_on_insert() {
+        let notifier = Arc::new(InsertNotifier::new());
+        let seen = notifier.inserts();
+
+        let writer = notifier.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            writer.notify();
+        });
+
+        let start = Instant::now();
+        notifier.wait(seen, Duration::from_secs(10));
+
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert_eq!(notifier.inserts(), seen + 1);
+    }
+}
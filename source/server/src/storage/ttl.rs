@@ -0,0 +1,196 @@
+use std::sync::Arc;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde_json::Value;
+
+use crate::io::logger::s_log;
+use crate::io::logger::EventCategory::Background;
+use crate::io::logger::EventSeverity::{Error, Info};
+use crate::lib::json::types::JsonObject;
+use crate::storage::options::{self, TtlOptions};
+use crate::storage::query;
+use crate::storage::registry::DatabaseRegistry;
+
+/// Delay between runs of the TTL reaper.
+pub const REAPER_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The outcome of a single run of the TTL reaper.
+#[derive(Default)]
+pub struct ReaperRun {
+    /// The amount of collections with a TTL that were checked.
+    pub collections: usize,
+    /// The amount of expired documents deleted.
+    pub expired: u64,
+    /// The amount of collections that could not be checked.
+    pub failed: usize,
+}
+
+/// True if the document has a date in the TTL field that expired at `now`. A document that would
+/// expire too far in the future for a date to represent never expires.
+pub fn is_expired(ttl: &TtlOptions, document: &JsonObject, now: DateTime<Utc>) -> bool {
+    let date = match query::lookup(document, &ttl.field).and_then(parse_date) {
+        Some(date) => date,
+        None => return false,
+    };
+
+    match options::seconds(ttl.expire_after_seconds).and_then(|ttl| date.checked_add_signed(ttl)) {
+        Some(expiry) => expiry <= now,
+        None => false,
+    }
+}
+
+/// The date in a JSON value, either an extended JSON date as decoded from BSON or an RFC 3339
+/// string.
+pub fn parse_date(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::String(s) => parse_rfc3339(s),
+        Value::Object(o) => match o.get("$date")? {
+            Value::String(s) => parse_rfc3339(s),
+            Value::Number(n) => Utc.timestamp_millis_opt(n.as_i64()?).single(),
+            Value::Object(long) => {
+                let millis = long.get("$numberLong")?.as_str()?.parse().ok()?;
+                Utc.timestamp_millis_opt(millis).single()
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn parse_rfc3339(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Deletes the expired documents of every collection with a TTL in every database.
+///
+/// Expired documents are found under the read lock of a collection, and the collection is only
+/// locked for writing while they are deleted. Failures are logged and skip the collection.
+pub fn reap(registry: &DatabaseRegistry) -> ReaperRun {
+    let mut run = ReaperRun::default();
+
+    for db in registry.databases() {
+        for collection in db.collections() {
+            let name = {
+                let collection = collection.read().unwrap();
+                if collection.options().ttl.is_none() {
+                    continue;
+                }
+                collection.name().original().clone()
+            };
+            run.collections += 1;
+
+            let now = Utc::now();
+            let expired = collection.read().unwrap().expired_documents(now);
+            let deleted =
+                expired.and_then(|ids| collection.write().unwrap().delete_expired(ids, now));
+
+            match deleted.and_then(|deleted| registry.balance_pool().map(|_| deleted)) {
+                Ok(deleted) => run.expired += deleted,
+                Err(e) => {
+                    run.failed += 1;
+                    s_log(
+                        Error,
+                        Background,
+                        &*format!(
+                            "[TTL-Reap] database={} collection={} {:?}",
+                            db.name(),
+                            name,
+                            e
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    run
+}
+
+/// Starts the background thread that deletes expired documents on a schedule, logging the
+/// outcome of every run.
+pub fn spawn_reaper(registry: Arc<DatabaseRegistry>) -> JoinHandle<()> {
+    thread::spawn(move || loop {
+        thread::sleep(REAPER_INTERVAL);
+
+        let start = Instant::now();
+        let run = reap(&registry);
+
+        s_log(
+            Info,
+            Background,
+            &*format!(
+                "[TTL-Run] collections={} expired={} failed={} elapsed_ms={}",
+                run.collections,
+                run.expired,
+                run.failed,
+                start.elapsed().as_millis()
+            ),
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_is_expired() {
+        let ttl = TtlOptions {
+            field: "session.lastSeen".to_string(),
+            expire_after_seconds: 60,
+        };
+        let now = Utc.timestamp(1_000_000, 0);
+
+        let document = |value: Value| {
+            json!({ "session": { "lastSeen": value } })
+                .as_object()
+                .unwrap()
+                .clone()
+        };
+
+        assert!(is_expired(
+            &ttl,
+            &document(json!("1970-01-12T13:45:00Z")),
+            now
+        ));
+        assert!(!is_expired(
+            &ttl,
+            &document(json!("1970-01-12T13:46:00Z")),
+            now
+        ));
+        assert!(is_expired(
+            &ttl,
+            &document(json!({ "$date": { "$numberLong": "999000000" } })),
+            now
+        ));
+        assert!(!is_expired(&ttl, &document(json!(999_000)), now));
+        assert!(!is_expired(&ttl, &JsonObject::new(), now));
+
+        let distant = TtlOptions {
+            field: "session.lastSeen".to_string(),
+            expire_after_seconds: chrono::Duration::max_value().num_seconds() as u64,
+        };
+        assert!(!is_expired(
+            &distant,
+            &document(json!("1970-01-12T13:45:00Z")),
+            now
+        ));
+
+        let overflowing = TtlOptions {
+            expire_after_seconds: u64::MAX,
+            ..distant
+        };
+        assert!(!is_expired(
+            &overflowing,
+            &document(json!("1970-01-12T13:45:00Z")),
+            now
+        ));
+    }
+}
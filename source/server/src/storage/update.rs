@@ -0,0 +1,491 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::mem;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use crate::lib::json::types::JsonObject;
+use crate::storage::ids::ID_FIELD;
+use crate::storage::query::{self, Filter};
+
+/// The field an array element is put in when it is matched against the condition of `$pull`.
+const ELEMENT_FIELD: &str = "element";
+/// The most nulls an array is padded with to set an element past its end.
+const MAX_ARRAY_PADDING: usize = 1024;
+
+#[derive(Debug, Clone)]
+/// A change to the value at a path.
+enum Change {
+    Set(Value),
+    Unset,
+    Inc(Number),
+    Mul(Number),
+    Min(Value),
+    Max(Value),
+    Push(Vec<Value>),
+    AddToSet(Vec<Value>),
+    /// Removes the array elements that match a filter on the element field.
+    Pull(Filter),
+    Rename(String),
+    /// Sets the current time, as milliseconds since the unix epoch if true or else as an RFC 3339
+    /// string.
+    CurrentDate(bool),
+}
+
+impl Change {
+    fn parse(operator: &str, path: &str, arg: &Value) -> Result<Change, String> {
+        let change = match operator {
+            "$set" => Change::Set(arg.clone()),
+            "$unset" => Change::Unset,
+            "$inc" | "$mul" => {
+                let n = match arg {
+                    Value::Number(n) => n.clone(),
+                    _ => return Err(format!("{}: {} must be a number", path, operator)),
+                };
+
+                if operator == "$inc" {
+                    Change::Inc(n)
+                } else {
+                    Change::Mul(n)
+                }
+            }
+            "$min" => Change::Min(arg.clone()),
+            "$max" => Change::Max(arg.clone()),
+            "$push" => Change::Push(each(path, arg)?),
+            "$addToSet" => Change::AddToSet(each(path, arg)?),
+            "$pull" => {
+                let mut condition = JsonObject::new();
+                condition.insert(ELEMENT_FIELD.to_string(), arg.clone());
+
+                Change::Pull(
+                    Filter::try_from(condition).map_err(|e| e.replacen(ELEMENT_FIELD, path, 1))?,
+                )
+            }
+            "$rename" => match arg {
+                Value::String(target) if target != path => Change::Rename(target.clone()),
+                _ => return Err(format!("{}: $rename must be another path", path)),
+            },
+            "$currentDate" => match arg {
+                Value::Bool(true) => Change::CurrentDate(false),
+                Value::Object(o) => match o.get("$type").and_then(Value::as_str) {
+                    Some("date") => Change::CurrentDate(false),
+                    Some("timestamp") => Change::CurrentDate(true),
+                    _ => return Err(format!("{}: $type must be date or timestamp", path)),
+                },
+                _ => {
+                    return Err(format!(
+                        "{}: $currentDate must be true or an object with a $type",
+                        path
+                    ))
+                }
+            },
+            _ => return Err(format!("unknown update operator {}", operator)),
+        };
+
+        Ok(change)
+    }
+
+    /// Applies the change to the value at a path into the document.
+    fn apply(&self, document: &mut Value, path: &str, now: DateTime<Utc>) -> Result<(), String> {
+        if let Change::Rename(target) = self {
+            let value = match parent(document, path, false)? {
+                Some((parent, key)) => remove(parent, key),
+                None => None,
+            };
+            if let Some(value) = value {
+                let (parent, key) = parent(document, target, true)?.unwrap();
+                set(parent, key, value, target)?;
+            }
+
+            return Ok(());
+        }
+        if let Change::Unset = self {
+            if let Some((parent, key)) = parent(document, path, false)? {
+                remove(parent, key);
+            }
+
+            return Ok(());
+        }
+
+        let (parent, key) = parent(document, path, true)?.unwrap();
+        let current = get(parent, key);
+
+        let value = match (self, current) {
+            (Change::Set(value), _) => value.clone(),
+            (Change::Inc(n), None) => Value::Number(n.clone()),
+            (Change::Inc(n), Some(Value::Number(current))) => {
+                arithmetic(current, n, i64::checked_add, |a, b| a + b)
+            }
+            (Change::Mul(n), None) => {
+                arithmetic(n, &Number::from(0), i64::checked_mul, |a, b| a * b)
+            }
+            (Change::Mul(n), Some(Value::Number(current))) => {
+                arithmetic(current, n, i64::checked_mul, |a, b| a * b)
+            }
+            (Change::Inc(_), Some(_)) | (Change::Mul(_), Some(_)) => {
+                return Err(format!("{}: the field is not a number", path))
+            }
+            (Change::Min(value), Some(current))
+                if query::compare(current, value) != Some(Ordering::Greater) =>
+            {
+                return Ok(())
+            }
+            (Change::Max(value), Some(current))
+                if query::compare(current, value) != Some(Ordering::Less) =>
+            {
+                return Ok(())
+            }
+            (Change::Min(value), _) | (Change::Max(value), _) => value.clone(),
+            (Change::Push(values), None) => Value::Array(values.clone()),
+            (Change::AddToSet(values), None) => {
+                let mut elements = Vec::new();
+                add_to_set(&mut elements, values);
+                Value::Array(elements)
+            }
+            (Change::Push(values), Some(Value::Array(elements))) => {
+                elements.extend(values.iter().cloned());
+                return Ok(());
+            }
+            (Change::AddToSet(values), Some(Value::Array(elements))) => {
+                add_to_set(elements, values);
+                return Ok(());
+            }
+            (Change::Pull(_), None) => return Ok(()),
+            (Change::Pull(condition), Some(Value::Array(elements))) => {
+                elements.retain(|element| {
+                    let mut wrapped = JsonObject::new();
+                    wrapped.insert(ELEMENT_FIELD.to_string(), element.clone());
+                    !condition.matches(&wrapped)
+                });
+                return Ok(());
+            }
+            (Change::Push(_), Some(_))
+            | (Change::AddToSet(_), Some(_))
+            | (Change::Pull(_), Some(_)) => {
+                return Err(format!("{}: the field is not an array", path))
+            }
+            (Change::CurrentDate(true), _) => Value::from(now.timestamp_millis()),
+            (Change::CurrentDate(false), _) => {
+                Value::from(now.to_rfc3339_opts(SecondsFormat::Millis, true))
+            }
+            (Change::Unset, _) | (Change::Rename(_), _) => unreachable!("applied above"),
+        };
+
+        set(parent, key, value, path)
+    }
+}
+
+/// The values given to `$push` or `$addToSet`, either a single value or the list of `$each`.
+fn each(path: &str, arg: &Value) -> Result<Vec<Value>, String> {
+    match arg.as_object().and_then(|o| o.get("$each")) {
+        Some(Value::Array(values)) if arg.as_object().unwrap().len() == 1 => Ok(values.clone()),
+        Some(_) => Err(format!(
+            "{}: $each must be the only field and an array",
+            path
+        )),
+        None => Ok(vec![arg.clone()]),
+    }
+}
+
+fn add_to_set(elements: &mut Vec<Value>, values: &[Value]) {
+    for value in values {
+        if !elements.iter().any(|element| query::equals(element, value)) {
+            elements.push(value.clone());
+        }
+    }
+}
+
+/// Combines two numbers as integers, or as floats if either is not an integer or the integer
+/// operation overflows.
+fn arithmetic(
+    a: &Number,
+    b: &Number,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Value {
+    match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) if int(a, b).is_some() => Value::from(int(a, b).unwrap()),
+        _ => Value::from(float(a.as_f64().unwrap(), b.as_f64().unwrap())),
+    }
+}
+
+/// The value that contains the value at a path, and the last part of the path. Objects missing
+/// along the path are created if `create` is true, and otherwise there is no parent.
+fn parent<'a, 'p>(
+    document: &'a mut Value,
+    path: &'p str,
+    create: bool,
+) -> Result<Option<(&'a mut Value, &'p str)>, String> {
+    let (parents, key) = match path.rsplit_once('.') {
+        Some((parents, key)) => (Some(parents), key),
+        None => (None, path),
+    };
+
+    let mut value = document;
+    for part in parents.into_iter().flat_map(|parents| parents.split('.')) {
+        value = match value {
+            Value::Object(o) => {
+                if !create && !o.contains_key(part) {
+                    return Ok(None);
+                }
+                o.entry(part.to_string())
+                    .or_insert_with(|| Value::Object(JsonObject::new()))
+            }
+            Value::Array(elements) => match part.parse::<usize>().ok() {
+                Some(index) if index < elements.len() => &mut elements[index],
+                _ if create => {
+                    return Err(format!("{}: {} is not an index of the array", path, part))
+                }
+                _ => return Ok(None),
+            },
+            _ if create => return Err(format!("{}: {} is not in an object", path, part)),
+            _ => return Ok(None),
+        };
+    }
+
+    Ok(Some((value, key)))
+}
+
+fn get<'a>(parent: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    match parent {
+        Value::Object(o) => o.get_mut(key),
+        Value::Array(elements) => elements.get_mut(key.parse::<usize>().ok()?),
+        _ => None,
+    }
+}
+
+/// Sets a field of an object, or an element of an array. An array is padded with nulls up to an
+/// index past its end, unless the index is more than `MAX_ARRAY_PADDING` past the end.
+fn set(parent: &mut Value, key: &str, value: Value, path: &str) -> Result<(), String> {
+    match parent {
+        Value::Object(o) => {
+            o.insert(key.to_string(), value);
+        }
+        Value::Array(elements) => {
+            let index = key
+                .parse::<usize>()
+                .ok()
+                .filter(|index| *index <= elements.len() + MAX_ARRAY_PADDING)
+                .ok_or_else(|| format!("{}: {} is not an index of the array", path, key))?;
+            if index >= elements.len() {
+                elements.resize(index + 1, Value::Null);
+            }
+            elements[index] = value;
+        }
+        _ => return Err(format!("{}: {} is not in an object", path, key)),
+    }
+
+    Ok(())
+}
+
+/// Removes a field of an object, keeping the order of the other fields. The element of an array
+/// is set to null instead, so the indexes of the other elements do not change.
+fn remove(parent: &mut Value, key: &str) -> Option<Value> {
+    match parent {
+        Value::Object(o) => {
+            // `Map::remove` swaps the last field into the place of the removed one when the map
+            // keeps its order, so the map is rebuilt without the field instead.
+            let mut removed = None;
+            *o = mem::take(o)
+                .into_iter()
+                .filter_map(|(k, v)| {
+                    if k == key {
+                        removed = Some(v);
+                        None
+                    } else {
+                        Some((k, v))
+                    }
+                })
+                .collect();
+
+            removed
+        }
+        Value::Array(_) => get(parent, key).map(|element| element.take()),
+        _ => None,
+    }
+}
+
+/// True if one path is the other or goes through it.
+fn overlaps(a: &str, b: &str) -> bool {
+    let (short, long) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    long == short || long.starts_with(&format!("{}.", short))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "JsonObject", into = "JsonObject")]
+/// The changes to make to documents, given as update operators mapped to the paths they change.
+///
+/// `$set`, `$unset`, `$inc`, `$mul`, `$min`, `$max`, `$rename` and `$currentDate` change a field,
+/// and `$push`, `$addToSet` and `$pull` change an array. `$push` and `$addToSet` add a value or
+/// every value of `$each`, and `$pull` removes the elements equal to a value or matching an
+/// object of query operators. A path cannot be changed twice, and `_id` cannot be changed.
+pub struct Update {
+    spec: JsonObject,
+    changes: Vec<(String, Change)>,
+}
+
+impl TryFrom<JsonObject> for Update {
+    type Error = String;
+
+    fn try_from(spec: JsonObject) -> Result<Self, Self::Error> {
+        let mut changes = Vec::new();
+        let mut paths: Vec<String> = Vec::new();
+
+        for (operator, fields) in spec.iter() {
+            let fields = match fields {
+                Value::Object(fields) => fields,
+                _ => return Err(format!("{} must be an object of paths", operator)),
+            };
+
+            for (path, arg) in fields.iter() {
+                let change = Change::parse(operator, path, arg)?;
+
+                let mut changed = vec![path.clone()];
+                if let Change::Rename(target) = &change {
+                    changed.push(target.clone());
+                }
+                for path in changed {
+                    if overlaps(&path, ID_FIELD) {
+                        return Err(format!("{} cannot be updated", ID_FIELD));
+                    }
+                    if let Some(other) = paths.iter().find(|other| overlaps(other, &path)) {
+                        return Err(format!("{} and {} cannot both be updated", other, path));
+                    }
+                    paths.push(path);
+                }
+
+                changes.push((path.clone(), change));
+            }
+        }
+
+        if changes.is_empty() {
+            return Err("an update must change at least one path".to_string());
+        }
+
+        Ok(Update { spec, changes })
+    }
+}
+
+impl From<Update> for JsonObject {
+    fn from(update: Update) -> Self {
+        update.spec
+    }
+}
+
+impl Update {
+    /// Applies every change to a document, in the order they were given. Fails if a change
+    /// does not apply to the type of a value in the document, in which case the document may be
+    /// partly changed.
+    pub fn apply(&self, document: JsonObject, now: DateTime<Utc>) -> Result<JsonObject, String> {
+        let mut document = Value::Object(document);
+        for (path, change) in self.changes.iter() {
+            change.apply(&mut document, path, now)?;
+        }
+
+        match document {
+            Value::Object(document) => Ok(document),
+            _ => unreachable!("changes never replace the document"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    use super::*;
+    use crate::lib::testing::object;
+
+    fn update(value: Value) -> Update {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_apply() {
+        let now = Utc.timestamp_millis_opt(1_600_000_000_000).unwrap();
+        let document = object(json!({
+            "_id": 1,
+            "name": "Ann",
+            "age": 30,
+            "score": 2.5,
+            "tags": ["a", "b"],
+            "scores": [1, 5, 9],
+            "best": 7,
+            "address": { "city": "Oslo" }
+        }));
+
+        let updated = update(json!({
+            "$set": { "address.zip": "0150", "tags.3": "d" },
+            "$unset": { "name": "" },
+            "$inc": { "age": 1, "visits": 1 },
+            "$mul": { "score": 2 },
+            "$min": { "low": 3 },
+            "$max": { "best": 4 },
+            "$addToSet": { "roles": { "$each": ["x", "x", "y"] } },
+            "$pull": { "scores": { "$gt": 6 } },
+            "$rename": { "address.city": "city" },
+            "$currentDate": { "seen": true, "stamp": { "$type": "timestamp" } }
+        }))
+        .apply(document, now)
+        .unwrap();
+
+        assert_eq!(
+            updated,
+            object(json!({
+                "_id": 1,
+                "age": 31,
+                "score": 5.0,
+                "tags": ["a", "b", null, "d"],
+                "scores": [1, 5],
+                "best": 7,
+                "address": { "zip": "0150" },
+                "visits": 1,
+                "low": 3,
+                "roles": ["x", "y"],
+                "city": "Oslo",
+                "seen": "2020-09-13T12:26:40.000Z",
+                "stamp": 1_600_000_000_000i64
+            }))
+        );
+
+        // Removing a field keeps the order of the others.
+        let keys: Vec<&str> = updated.keys().take(3).map(String::as_str).collect();
+        assert_eq!(keys, ["_id", "age", "score"]);
+
+        // Arrays are not padded far past their end.
+        assert!(update(json!({ "$set": { "tags.4000000000": 1 } }))
+            .apply(updated.clone(), now)
+            .is_err());
+
+        let result = update(json!({ "$push": { "name": 1 } })).apply(updated, now);
+        assert!(result.is_ok());
+        assert!(update(json!({ "$push": { "age": 1 } }))
+            .apply(result.unwrap(), now)
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_update() {
+        let invalid = [
+            json!({}),
+            json!({ "$set": 1 }),
+            json!({ "$replace": { "a": 1 } }),
+            json!({ "$set": { "_id": 2 } }),
+            json!({ "$rename": { "a": "_id" } }),
+            json!({ "$set": { "a.b": 1 }, "$unset": { "a": "" } }),
+            json!({ "$inc": { "a": "1" } }),
+            json!({ "$push": { "a": { "$each": 1 } } }),
+        ];
+
+        for spec in invalid.iter() {
+            assert!(
+                serde_json::from_value::<Update>(spec.clone()).is_err(),
+                "{}",
+                spec
+            );
+        }
+    }
+}
@@ -1,28 +1,161 @@
+use crate::page::page::{META_PAGE_EXT, OVERFLOW_PAGE_EXT};
+
+/// Infix of the page files that hold the version history of a collection.
+pub const HISTORY_FILE_EXT: &str = "history";
+/// The longest allowed collection name.
+pub const MAX_COLLECTION_NAME_LEN: usize = 64;
+/// Names that cannot be used as file names on every platform.
+const RESERVED_FILE_NAMES: [&str; 22] = [
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+#[derive(Debug, PartialEq)]
+/// The reason a collection name was rejected.
+pub enum CollectionNameError {
+    Empty,
+    /// The name is longer than [`MAX_COLLECTION_NAME_LEN`].
+    TooLong(usize),
+    /// Names may only contain ASCII letters, digits, `_` and `-`.
+    InvalidCharacter(char),
+    /// Names must start with a letter or digit.
+    InvalidStart(char),
+    /// The name is reserved by the filesystem.
+    Reserved(String),
+}
+
 #[derive(Debug, Clone)]
 /// Utility for formatting a collection name into several useful filename formats.
-pub struct CollectionNameFormatter(String);
+///
+/// File names are relative to the data directory. The files of a collection that belongs to a
+/// database are stored in the subdirectory of the database.
+pub struct CollectionNameFormatter {
+    name: String,
+    /// The database the collection belongs to.
+    database: Option<String>,
+}
 
 impl CollectionNameFormatter {
     pub fn new<T>(s: T) -> Self
     where
         T: AsRef<str>,
     {
-        CollectionNameFormatter(s.as_ref().into())
+        CollectionNameFormatter {
+            name: s.as_ref().into(),
+            database: None,
+        }
+    }
+
+    /// Creates a formatter for a name given by a user, rejecting names that are unsafe to use in
+    /// page file names.
+    pub fn validate<T>(s: T) -> Result<Self, CollectionNameError>
+    where
+        T: AsRef<str>,
+    {
+        let name = s.as_ref();
+
+        let first = match name.chars().next() {
+            Some(c) => c,
+            None => return Err(CollectionNameError::Empty),
+        };
+        if name.len() > MAX_COLLECTION_NAME_LEN {
+            return Err(CollectionNameError::TooLong(name.len()));
+        }
+        if let Some(c) = name
+            .chars()
+            .find(|c| !(c.is_ascii_alphanumeric() || *c == '_' || *c == '-'))
+        {
+            return Err(CollectionNameError::InvalidCharacter(c));
+        }
+        if !first.is_ascii_alphanumeric() {
+            return Err(CollectionNameError::InvalidStart(first));
+        }
+        if RESERVED_FILE_NAMES.contains(&&*name.to_ascii_lowercase()) {
+            return Err(CollectionNameError::Reserved(name.to_string()));
+        }
+
+        Ok(CollectionNameFormatter::new(name))
+    }
+
+    /// Places the collection files in the subdirectory of a database.
+    pub fn in_database<T>(mut self, database: T) -> Self
+    where
+        T: AsRef<str>,
+    {
+        self.database = Some(database.as_ref().into());
+        self
     }
 
     /// Get the raw collection name.
     pub fn original(&self) -> &String {
-        &self.0
+        &self.name
     }
 
     /// Convert to the raw collection name.
     pub fn into_original(self) -> String {
-        self.0
+        self.name
+    }
+
+    /// The formatter of the page files that hold the version history of the collection. History
+    /// files are named `<collection>.history.<id>`, which no collection name can collide with.
+    pub fn history(&self) -> Self {
+        CollectionNameFormatter {
+            name: format!("{}.{}", self.name, HISTORY_FILE_EXT),
+            database: self.database.clone(),
+        }
+    }
+
+    /// The directory of the collection files, relative to the data directory.
+    pub fn directory(&self) -> &str {
+        self.database.as_deref().unwrap_or("")
     }
 
     /// Get the page file name based on the page id.
     pub fn as_page_file_name(&self, page_id: u32) -> String {
-        format!("{}.{}", self.0, page_id)
+        format!("{}.{}", self.file_stem(), page_id)
+    }
+
+    /// Get the file name of a chunk of a record stored in overflow pages.
+    pub fn as_overflow_file_name(&self, page_id: u32, slot: u32, chunk: u32) -> String {
+        format!(
+            "{}.{}.{}.{}",
+            self.as_page_file_name(page_id),
+            OVERFLOW_PAGE_EXT,
+            slot,
+            chunk
+        )
+    }
+
+    /// Get the page metadata file name based on the page id.
+    pub fn as_meta_file_name(&self, page_id: u32) -> String {
+        format!("{}.{}", self.as_page_file_name(page_id), META_PAGE_EXT)
+    }
+
+    /// The page id of a page, page metadata or overflow page file of the collection, given its
+    /// name within the collection directory.
+    pub fn page_id_of(&self, file: &str) -> Option<u32> {
+        let rest = file.strip_prefix(&*self.name)?.strip_prefix('.')?;
+
+        rest.split('.').next().unwrap().parse().ok()
+    }
+
+    /// True if the file, named within the collection directory, is a page, page metadata or
+    /// overflow page file of the collection.
+    pub fn owns_file(&self, file: &str) -> bool {
+        self.page_id_of(file).is_some()
+    }
+
+    /// The name of a file of the collection after renaming the collection.
+    pub fn rename_file(&self, file: &str, to: &CollectionNameFormatter) -> String {
+        format!("{}{}", to.file_stem(), &file[self.file_stem().len()..])
+    }
+
+    /// The common prefix of every file name of the collection.
+    fn file_stem(&self) -> String {
+        match &self.database {
+            Some(database) => format!("{}/{}", database, self.name),
+            None => self.name.clone(),
+        }
     }
 }
 
@@ -38,4 +171,78 @@ mod test {
 
         assert_eq!(page_file_name, "users.16");
     }
+
+    #[test]
+    fn test_collection_name_as_meta_file_name() {
+        let col_name = CollectionNameFormatter::new("users");
+
+        let meta_file_name = col_name.as_meta_file_name(16);
+
+        assert_eq!(meta_file_name, "users.16.meta");
+    }
+
+    #[test]
+    fn test_collection_name_as_overflow_file_name() {
+        let col_name = CollectionNameFormatter::new("users");
+
+        let overflow_file_name = col_name.as_overflow_file_name(16, 3, 0);
+
+        assert_eq!(overflow_file_name, "users.16.overflow.3.0");
+    }
+
+    #[test]
+    fn test_collection_name_validate() {
+        assert!(CollectionNameFormatter::validate("user-events_2").is_ok());
+        assert_eq!(
+            CollectionNameFormatter::validate("").err(),
+            Some(CollectionNameError::Empty)
+        );
+        assert_eq!(
+            CollectionNameFormatter::validate("../users").err(),
+            Some(CollectionNameError::InvalidCharacter('.'))
+        );
+        assert_eq!(
+            CollectionNameFormatter::validate("_admin").err(),
+            Some(CollectionNameError::InvalidStart('_'))
+        );
+        assert_eq!(
+            CollectionNameFormatter::validate("NUL").err(),
+            Some(CollectionNameError::Reserved("NUL".to_string()))
+        );
+        assert_eq!(
+            CollectionNameFormatter::validate("x".repeat(65)).err(),
+            Some(CollectionNameError::TooLong(65))
+        );
+    }
+
+    #[test]
+    fn test_collection_name_owns_file() {
+        let col_name = CollectionNameFormatter::new("users");
+
+        assert!(col_name.owns_file("users.16"));
+        assert!(col_name.owns_file("users.16.overflow.3.0"));
+        assert!(!col_name.owns_file("users2.16"));
+        assert!(!col_name.owns_file("users.catalog"));
+        assert!(!col_name.owns_file("users.history.16"));
+        assert!(col_name.history().owns_file("users.history.16"));
+        assert_eq!(
+            col_name.rename_file("users.16.meta", &CollectionNameFormatter::new("people")),
+            "people.16.meta"
+        );
+    }
+
+    #[test]
+    fn test_collection_name_in_database() {
+        let col_name = CollectionNameFormatter::new("users").in_database("shop");
+
+        assert_eq!(col_name.as_meta_file_name(16), "shop/users.16.meta");
+        assert_eq!(col_name.page_id_of("users.16.overflow.3.0"), Some(16));
+        assert_eq!(
+            col_name.rename_file(
+                "shop/users.16",
+                &CollectionNameFormatter::new("people").in_database("shop")
+            ),
+            "shop/people.16"
+        );
+    }
 }
@@ -0,0 +1,267 @@
+use std::convert::TryFrom;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lib::json::types::JsonObject;
+use crate::storage::ids::ID_FIELD;
+use crate::storage::query::{self, Filter};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A step of a view pipeline. Every stage takes the documents of the previous stage, starting
+/// with the documents of the source collection that match the view query.
+pub enum Stage {
+    /// Keeps the documents that match a filter.
+    #[serde(rename = "$match")]
+    Match(Filter),
+    /// Reshapes every document.
+    #[serde(rename = "$project")]
+    Project(Projection),
+    /// Drops the first documents.
+    #[serde(rename = "$skip")]
+    Skip(usize),
+    /// Keeps the first documents.
+    #[serde(rename = "$limit")]
+    Limit(usize),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "JsonObject", into = "JsonObject")]
+/// The fields kept or removed from documents, given as a path to each field mapped to `1` to
+/// keep only the listed fields, or to `0` to remove them. `_id` is kept unless it is mapped to
+/// `0`, which is the only exclusion allowed in a projection that keeps fields.
+pub struct Projection {
+    spec: JsonObject,
+    /// True if only the listed fields are kept.
+    include: bool,
+    /// Paths of the listed fields, without `_id`.
+    paths: Vec<String>,
+    keep_id: bool,
+}
+
+impl TryFrom<JsonObject> for Projection {
+    type Error = String;
+
+    fn try_from(spec: JsonObject) -> Result<Self, Self::Error> {
+        let mut include = None;
+        let mut paths = Vec::new();
+        let mut keep_id = true;
+
+        for (path, value) in spec.iter() {
+            let keep = match value {
+                Value::Bool(keep) => *keep,
+                Value::Number(n) if n.as_f64() == Some(0.0) => false,
+                Value::Number(n) if n.as_f64() == Some(1.0) => true,
+                _ => return Err(format!("{} must be 0 or 1", path)),
+            };
+
+            if path == ID_FIELD {
+                keep_id = keep;
+                continue;
+            }
+            if include.map_or(false, |include| include != keep) {
+                return Err("a projection cannot both keep and remove fields".to_string());
+            }
+
+            include = Some(keep);
+            paths.push(path.clone());
+        }
+
+        Ok(Projection {
+            spec,
+            include: include.unwrap_or(false),
+            paths,
+            keep_id,
+        })
+    }
+}
+
+impl From<Projection> for JsonObject {
+    fn from(projection: Projection) -> Self {
+        projection.spec
+    }
+}
+
+impl Projection {
+    pub fn apply(&self, document: JsonObject) -> JsonObject {
+        let mut projected = if self.include {
+            let mut projected = JsonObject::new();
+            for path in self.paths.iter() {
+                if let Some(value) = query::lookup(&document, path) {
+                    insert_path(&mut projected, path, value.clone());
+                }
+            }
+            if let Some(id) = document.get(ID_FIELD) {
+                projected.insert(ID_FIELD.to_string(), id.clone());
+            }
+
+            projected
+        } else {
+            let mut projected = document;
+            for path in self.paths.iter() {
+                remove_path(&mut projected, path);
+            }
+
+            projected
+        };
+
+        if !self.keep_id {
+            projected.remove(ID_FIELD);
+        }
+
+        projected
+    }
+}
+
+/// Sets the value at a path, creating the objects along the path that do not exist.
+fn insert_path(document: &mut JsonObject, path: &str, value: Value) {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (Some(parent), key),
+        None => (None, path),
+    };
+
+    let mut object = document;
+    for part in parent.into_iter().flat_map(|parent| parent.split('.')) {
+        let next = object
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Object(JsonObject::new()));
+        object = match next {
+            Value::Object(o) => o,
+            _ => return,
+        };
+    }
+
+    object.insert(key.to_string(), value);
+}
+
+/// Removes the value at a path if there is one.
+fn remove_path(document: &mut JsonObject, path: &str) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let key = parts.pop().unwrap();
+
+    let mut object = document;
+    for part in parts {
+        object = match object.get_mut(part) {
+            Some(Value::Object(o)) => o,
+            _ => return,
+        };
+    }
+
+    object.remove(key);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A saved query over a source collection. Reading a view reads the documents of the source
+/// collection that match the query and runs them through the pipeline.
+pub struct ViewDefinition {
+    /// The collection the view reads from.
+    pub source: String,
+    #[serde(default)]
+    pub query: Filter,
+    #[serde(default)]
+    pub pipeline: Vec<Stage>,
+}
+
+impl ViewDefinition {
+    /// Runs the documents of the source collection through the query and the pipeline.
+    pub fn run(&self, documents: Vec<JsonObject>) -> Vec<JsonObject> {
+        let mut documents: Vec<JsonObject> = documents
+            .into_iter()
+            .filter(|document| self.query.matches(document))
+            .collect();
+
+        for stage in self.pipeline.iter() {
+            documents = match stage {
+                Stage::Match(filter) => documents
+                    .into_iter()
+                    .filter(|document| filter.matches(document))
+                    .collect(),
+                Stage::Project(projection) => documents
+                    .into_iter()
+                    .map(|document| projection.apply(document))
+                    .collect(),
+                Stage::Skip(n) => documents.into_iter().skip(*n).collect(),
+                Stage::Limit(n) => documents.into_iter().take(*n).collect(),
+            };
+        }
+
+        documents
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// The catalog record of a single view.
+pub struct ViewEntry {
+    pub name: String,
+    #[serde(flatten)]
+    pub definition: ViewDefinition,
+    /// When the view was created, in seconds since the unix epoch.
+    pub created: i64,
+}
+
+impl ViewEntry {
+    /// A record for a view created now.
+    pub fn new(name: String, definition: ViewDefinition) -> Self {
+        ViewEntry {
+            name,
+            definition,
+            created: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::lib::testing::object;
+
+    #[test]
+    fn test_run_pipeline() {
+        let view: ViewDefinition = serde_json::from_value(json!({
+            "source": "users",
+            "query": { "active": true },
+            "pipeline": [
+                { "$project": { "name": 1, "address.city": 1 } },
+                { "$skip": 1 },
+                { "$limit": 1 }
+            ]
+        }))
+        .unwrap();
+
+        let documents = vec![
+            object(json!({ "_id": 0, "name": "Ann", "active": true })),
+            object(json!({ "_id": 1, "name": "Bob", "active": false })),
+            object(
+                json!({ "_id": 2, "name": "Eve", "active": true, "address": { "city": "Oslo", "zip": "0150" } }),
+            ),
+            object(json!({ "_id": 3, "name": "Kim", "active": true })),
+        ];
+
+        assert_eq!(
+            view.run(documents),
+            vec![object(
+                json!({ "_id": 2, "name": "Eve", "address": { "city": "Oslo" } })
+            )]
+        );
+    }
+
+    #[test]
+    fn test_projection_mixed() {
+        let result: Result<Projection, _> = serde_json::from_value(json!({ "name": 1, "age": 0 }));
+        assert!(result.is_err());
+
+        let projection: Projection =
+            serde_json::from_value(json!({ "_id": 0, "address.zip": 0 })).unwrap();
+        assert_eq!(
+            projection.apply(object(
+                json!({ "_id": 1, "address": { "city": "Oslo", "zip": "0150" } })
+            )),
+            object(json!({ "address": { "city": "Oslo" } }))
+        );
+    }
+}